@@ -0,0 +1,198 @@
+//! Counts the heap allocations made by each kind of operation once the device is warmed up, as in
+//! a steady-state frame loop.
+//!
+//! Run with `cargo bench --bench allocations`. Each operation is compared with the same work done
+//! through `wgpu` directly, since `wgpu` makes allocations of its own, such as boxing the callbacks
+//! it is given. The difference is what `wgpu_async` adds, which should be zero, apart from the box
+//! `do_async` puts its callback in.
+//!
+//! `tests/allocations.rs` asserts the same, so that an allocation added to an operation fails the
+//! tests rather than only showing up here.
+
+#[cfg(not(target_arch = "wasm32"))]
+mod native {
+    use std::alloc::{GlobalAlloc, Layout, System};
+    use std::future::Future;
+    use std::ops::Deref;
+    use std::pin::pin;
+    use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+    use std::sync::Arc;
+    use std::task::{Context, Poll, Wake, Waker};
+    use std::thread::Thread;
+
+    use wgpu_async::{AsyncDevice, AsyncDeviceBuilder, AsyncQueue, Task};
+
+    struct CountingAllocator;
+
+    static ALLOCATIONS: AtomicUsize = AtomicUsize::new(0);
+
+    // SAFETY: defers to the system allocator
+    unsafe impl GlobalAlloc for CountingAllocator {
+        unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+            ALLOCATIONS.fetch_add(1, Ordering::Relaxed);
+            System.alloc(layout)
+        }
+
+        unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+            System.dealloc(ptr, layout)
+        }
+
+        unsafe fn realloc(&self, ptr: *mut u8, layout: Layout, new_size: usize) -> *mut u8 {
+            ALLOCATIONS.fetch_add(1, Ordering::Relaxed);
+            System.realloc(ptr, layout, new_size)
+        }
+    }
+
+    #[global_allocator]
+    static GLOBAL: CountingAllocator = CountingAllocator;
+
+    const WARMUP: usize = 100;
+    const ITERATIONS: usize = 1000;
+
+    fn setup(builder: AsyncDeviceBuilder) -> (AsyncDevice, AsyncQueue) {
+        pollster::block_on(async {
+            let instance = wgpu::Instance::new(wgpu::InstanceDescriptor::default());
+            let adapter = instance
+                .request_adapter(&wgpu::RequestAdapterOptions {
+                    power_preference: wgpu::PowerPreference::HighPerformance,
+                    compatible_surface: None,
+                    force_fallback_adapter: true,
+                })
+                .await
+                .expect("missing adapter");
+            let (device, queue) = adapter
+                .request_device(
+                    &wgpu::DeviceDescriptor {
+                        required_features: wgpu::Features::empty(),
+                        required_limits: adapter.limits(),
+                        label: None,
+                        memory_hints: wgpu::MemoryHints::default(),
+                    },
+                    None,
+                )
+                .await
+                .expect("missing device");
+
+            builder
+                .wrap(Arc::new(device), Arc::new(queue))
+                .expect("failed to wrap device")
+        })
+    }
+
+    /// Wakes the thread running [`block_on`].
+    struct Unpark(Thread);
+
+    impl Wake for Unpark {
+        fn wake(self: Arc<Self>) {
+            self.0.unpark()
+        }
+    }
+
+    thread_local! {
+        static WAKER: Waker = Waker::from(Arc::new(Unpark(std::thread::current())));
+    }
+
+    /// Runs a future to completion on this thread, without allocating so as not to be counted.
+    fn block_on<F: Future>(future: F) -> F::Output {
+        let mut future = pin!(future);
+        WAKER.with(|waker| {
+            let mut cx = Context::from_waker(waker);
+            loop {
+                match future.as_mut().poll(&mut cx) {
+                    Poll::Ready(output) => return output,
+                    Poll::Pending => std::thread::park(),
+                }
+            }
+        })
+    }
+
+    /// Gives the mean number of allocations made per call of `f`, once warmed up.
+    fn allocations_per_call(mut f: impl FnMut()) -> f64 {
+        for _ in 0..WARMUP {
+            f();
+        }
+
+        let before = ALLOCATIONS.load(Ordering::Relaxed);
+        for _ in 0..ITERATIONS {
+            f();
+        }
+        let allocations = ALLOCATIONS.load(Ordering::Relaxed) - before;
+
+        allocations as f64 / ITERATIONS as f64
+    }
+
+    pub fn main() {
+        let (device, queue) = setup(AsyncDeviceBuilder::new());
+        let (dispatched_device, _) = setup(AsyncDeviceBuilder::new().dispatcher(Task::run));
+
+        let descriptor = wgpu::BufferDescriptor {
+            label: None,
+            size: 256,
+            usage: wgpu::BufferUsages::MAP_READ,
+            mapped_at_creation: false,
+        };
+        let buffer = device.create_buffer(&descriptor);
+        let dispatched_buffer = dispatched_device.create_buffer(&descriptor);
+
+        // Stands in for whatever state a callback given to `wgpu` would capture
+        static DONE: AtomicBool = AtomicBool::new(false);
+        let done = &DONE;
+
+        let raw_map = |buffer: &wgpu::Buffer| {
+            buffer.slice(..).map_async(wgpu::MapMode::Read, move |res| {
+                done.store(res.is_ok(), Ordering::Relaxed)
+            });
+            device.poll(wgpu::Maintain::Wait);
+            buffer.unmap();
+        };
+
+        // Each case is run through `wgpu` directly, then through `wgpu_async`
+        type Case<'a> = (&'a str, &'a mut dyn FnMut(), &'a mut dyn FnMut());
+        let cases: [Case; 5] = [
+            ("do_async", &mut || {}, &mut || {
+                block_on(device.do_async(|callback| callback(()))).expect("operation failed")
+            }),
+            ("do_async_unboxed", &mut || {}, &mut || {
+                block_on(device.do_async_unboxed(|callback| callback.complete(())))
+                    .expect("operation failed")
+            }),
+            ("map_async", &mut || raw_map(&buffer), &mut || {
+                block_on(buffer.slice(..).map_async(wgpu::MapMode::Read)).expect("map failed");
+                buffer.unmap();
+            }),
+            (
+                "map_async with a dispatcher",
+                &mut || raw_map(&buffer),
+                &mut || {
+                    block_on(dispatched_buffer.slice(..).map_async(wgpu::MapMode::Read))
+                        .expect("map failed");
+                    dispatched_buffer.unmap();
+                },
+            ),
+            (
+                "submit",
+                &mut || {
+                    queue.deref().submit([]);
+                    queue.on_submitted_work_done(move || done.store(true, Ordering::Relaxed));
+                    device.poll(wgpu::Maintain::Wait);
+                },
+                &mut || block_on(queue.submit([])).expect("submit failed"),
+            ),
+        ];
+
+        println!("allocations per call: wgpu / wgpu_async / added by wgpu_async");
+        for (name, raw, wrapped) in cases {
+            let raw = allocations_per_call(raw);
+            let wrapped = allocations_per_call(wrapped);
+            println!("{name}: {raw:.2} / {wrapped:.2} / {:.2}", wrapped - raw);
+        }
+    }
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+fn main() {
+    native::main()
+}
+
+#[cfg(target_arch = "wasm32")]
+fn main() {}
@@ -1,16 +1,143 @@
-use crate::{async_device::AsyncDevice, WgpuFuture};
+use crate::pending::OperationKind;
+use crate::{async_device::AsyncDevice, Error, WgpuFuture};
+use std::fmt::{self, Write};
+use std::future::Future;
 use std::ops::{Deref, DerefMut, RangeBounds};
-use wgpu::{BufferAddress, BufferAsyncError};
+use std::sync::atomic::{AtomicU8, Ordering};
+use std::sync::Arc;
+use wgpu::BufferAddress;
+
+const UNMAPPED: u8 = 0;
+/// A mapping has been started, but hasn't completed.
+const MAPPING: u8 = 1;
+const MAPPED: u8 = 2;
+
+/// A buffer which keeps track of whether it is mapped, shared with mapping callbacks, which may need
+/// to unmap it.
+///
+/// The buffer may also be unmapped through [`wgpu::Buffer::unmap`], which isn't seen here, so a
+/// buffer tracked as mapped is checked with `wgpu` before being relied on.
+#[derive(Debug)]
+pub(crate) struct MappableBuffer {
+    buffer: wgpu::Buffer,
+    map_state: AtomicU8,
+}
+
+impl MappableBuffer {
+    pub(crate) fn new(buffer: wgpu::Buffer, mapped_at_creation: bool) -> Self {
+        Self {
+            buffer,
+            map_state: AtomicU8::new(if mapped_at_creation { MAPPED } else { UNMAPPED }),
+        }
+    }
+
+    pub(crate) fn is_mapped(&self) -> bool {
+        self.sync_map_state();
+        self.map_state.load(Ordering::Acquire) == MAPPED
+    }
+
+    /// Notices the buffer having been unmapped through [`wgpu::Buffer::unmap`] since it was mapped.
+    ///
+    /// Mappings which haven't completed aren't checked, since `wgpu` fails them when the buffer is
+    /// unmapped, and their callback then tracks the buffer as unmapped.
+    fn sync_map_state(&self) {
+        if self.map_state.load(Ordering::Acquire) == MAPPED
+            && wgpu_map_range(&self.buffer).is_some_and(|range| range.is_unmapped())
+        {
+            // Lose to anything which changed the state in the meantime
+            let _ = self.map_state.compare_exchange(
+                MAPPED,
+                UNMAPPED,
+                Ordering::AcqRel,
+                Ordering::Acquire,
+            );
+        }
+    }
+
+    pub(crate) fn unmap(&self) {
+        self.buffer.unmap();
+        self.map_state.store(UNMAPPED, Ordering::Release);
+    }
+}
+
+/// The range of a buffer which `wgpu` has mapped, or been asked to map, as written by its `Debug`
+/// impl, which is `0..0` when the buffer isn't mapped.
+struct MapRange {
+    bytes: [u8; 48],
+    len: usize,
+}
+
+impl MapRange {
+    fn is_unmapped(&self) -> bool {
+        &self.bytes[..self.len] == b"0..0"
+    }
+}
+
+/// Reads the range of `buffer` which `wgpu` considers mapped.
+///
+/// `wgpu` 23 has no `Buffer::map_state`, and panics when mapping a buffer it considers mapped, so
+/// this is read from the `initial_range` field of the buffer's `Debug` output, without allocating.
+/// Gives `None` if the field can't be found, for example while another thread has the buffer's map
+/// context locked.
+fn wgpu_map_range(buffer: &wgpu::Buffer) -> Option<MapRange> {
+    const FIELD: &[u8] = b"initial_range: ";
+
+    struct Scanner {
+        /// How much of `FIELD` has been matched.
+        matched: usize,
+        range: MapRange,
+        done: bool,
+    }
+
+    impl Write for Scanner {
+        fn write_str(&mut self, s: &str) -> fmt::Result {
+            for byte in s.bytes() {
+                if self.matched < FIELD.len() {
+                    self.matched = if byte == FIELD[self.matched] {
+                        self.matched + 1
+                    } else {
+                        usize::from(byte == FIELD[0])
+                    };
+                } else if byte == b',' || byte == b' ' {
+                    // Stop formatting the rest of the buffer
+                    self.done = true;
+                    return Err(fmt::Error);
+                } else if self.range.len < self.range.bytes.len() {
+                    self.range.bytes[self.range.len] = byte;
+                    self.range.len += 1;
+                } else {
+                    return Err(fmt::Error);
+                }
+            }
+            Ok(())
+        }
+    }
+
+    let mut scanner = Scanner {
+        matched: 0,
+        range: MapRange {
+            bytes: [0; 48],
+            len: 0,
+        },
+        done: false,
+    };
+    let _ = write!(scanner, "{buffer:?}");
+    scanner.done.then_some(scanner.range)
+}
 
 /// A wrapper around a [`wgpu::Buffer`] which shadows some methods to allow for async
 /// mapping using Rust's `async` API.
+///
+/// Only derefs to a shared [`wgpu::Buffer`], since the buffer is shared with the callbacks of its
+/// mappings. Every method of [`wgpu::Buffer`] takes `&self`, so nothing is lost, but code which used
+/// the `DerefMut` and `AsMut` impls of earlier versions should reborrow with `&*buffer` instead.
 #[derive(Debug)]
 pub struct AsyncBuffer
 where
     Self: wgpu::WasmNotSend,
 {
     pub(crate) device: AsyncDevice,
-    pub(crate) buffer: wgpu::Buffer,
+    pub(crate) buffer: Arc<MappableBuffer>,
 }
 
 impl AsyncBuffer {
@@ -18,23 +145,88 @@ impl AsyncBuffer {
     /// except wraps the result in an [`AsyncBufferSlice`] so that the `map_async` method can be
     /// awaited.
     pub fn slice<S: RangeBounds<BufferAddress>>(&self, bounds: S) -> AsyncBufferSlice<'_> {
-        let buffer_slice = self.buffer.slice(bounds);
+        let buffer_slice = self.buffer.buffer.slice(bounds);
         AsyncBufferSlice {
             device: self.device.clone(),
+            buffer: &self.buffer,
             buffer_slice,
         }
     }
+
+    /// Maps the bytes within `bounds` for reading, giving a guard which derefs to the mapped bytes,
+    /// and unmaps the buffer when dropped.
+    ///
+    /// Borrows the buffer mutably until the guard is dropped, so that mapping the buffer again
+    /// while it is mapped doesn't compile:
+    ///
+    /// ```compile_fail
+    /// # async fn read(buffer: &mut wgpu_async::AsyncBuffer) {
+    /// let bytes = buffer.map_read(..128).await.unwrap();
+    /// let other = buffer.map_read(128..); // Error: `buffer` is still borrowed by `bytes`
+    /// drop(bytes);
+    /// # }
+    /// ```
+    ///
+    /// Otherwise fails as [`AsyncBufferSlice::map_async`] does.
+    #[track_caller]
+    pub fn map_read<S: RangeBounds<BufferAddress>>(
+        &mut self,
+        bounds: S,
+    ) -> impl Future<Output = Result<MappedRead<'_>, Error>> {
+        let slice = self.slice(bounds);
+        let mapping = slice.map_async(wgpu::MapMode::Read);
+        async move {
+            mapping.await?;
+            Ok(MappedRead {
+                buffer: slice.buffer,
+                view: Some(slice.buffer_slice.get_mapped_range()),
+            })
+        }
+    }
+
+    /// Maps the bytes within `bounds` for writing, giving a guard which derefs to the mapped
+    /// bytes, and unmaps the buffer when dropped, making the bytes written visible to the GPU.
+    ///
+    /// Borrows the buffer and fails as [`AsyncBuffer::map_read`] does.
+    #[track_caller]
+    pub fn map_write<S: RangeBounds<BufferAddress>>(
+        &mut self,
+        bounds: S,
+    ) -> impl Future<Output = Result<MappedWrite<'_>, Error>> {
+        let slice = self.slice(bounds);
+        let mapping = slice.map_async(wgpu::MapMode::Write);
+        async move {
+            mapping.await?;
+            Ok(MappedWrite {
+                buffer: slice.buffer,
+                view: Some(slice.buffer_slice.get_mapped_range_mut()),
+            })
+        }
+    }
+
+    /// Unmaps the buffer, in the same way a call to [`wgpu::Buffer::unmap`] would, except this
+    /// buffer then knows that it is no longer mapped, which is checked at the end of an
+    /// [`AsyncDevice::scope`].
+    ///
+    /// Unmapping through the deref, with [`wgpu::Buffer::unmap`], also works, but is only noticed
+    /// the next time the buffer is mapped or checked for being mapped.
+    pub fn unmap(&self) {
+        self.buffer.unmap()
+    }
+
+    /// Whether the buffer is mapped, either at creation or by a call to
+    /// [`AsyncBufferSlice::map_async`] which has completed, and hasn't since been unmapped using
+    /// [`AsyncBuffer::unmap`], or by dropping a guard given by [`AsyncBuffer::map_read`] or
+    /// [`AsyncBuffer::map_write`], or through [`wgpu::Buffer::unmap`].
+    pub fn is_mapped(&self) -> bool {
+        self.buffer.is_mapped()
+    }
 }
 impl Deref for AsyncBuffer {
     type Target = wgpu::Buffer;
 
     fn deref(&self) -> &Self::Target {
-        &self.buffer
-    }
-}
-impl DerefMut for AsyncBuffer {
-    fn deref_mut(&mut self) -> &mut Self::Target {
-        &mut self.buffer
+        &self.buffer.buffer
     }
 }
 impl<T> AsRef<T> for AsyncBuffer
@@ -46,14 +238,6 @@ where
         self.deref().as_ref()
     }
 }
-impl<T> AsMut<T> for AsyncBuffer
-where
-    <AsyncBuffer as Deref>::Target: AsMut<T>,
-{
-    fn as_mut(&mut self) -> &mut T {
-        self.deref_mut().as_mut()
-    }
-}
 
 /// A smart-pointer wrapper around a [`wgpu::BufferSlice`], offering a `map_async` method than can be `await`ed.
 #[derive(Debug)]
@@ -62,13 +246,65 @@ where
     Self: wgpu::WasmNotSend,
 {
     device: AsyncDevice,
+    buffer: &'a Arc<MappableBuffer>,
     buffer_slice: wgpu::BufferSlice<'a>,
 }
 impl<'a> AsyncBufferSlice<'a> {
     /// An awaitable version of [`wgpu::Buffer::map_async`].
-    pub fn map_async(&self, mode: wgpu::MapMode) -> WgpuFuture<Result<(), BufferAsyncError>> {
+    ///
+    /// A failure to map resolves the future with [`Error::BufferAsync`]. If the buffer is already
+    /// mapped, or being mapped, the future resolves with [`Error::AlreadyMapped`] instead, since
+    /// `wgpu` only allows one mapping of a buffer at a time. Whether the buffer is mapped is judged
+    /// as [`AsyncBuffer::is_mapped`] does, so a buffer unmapped through [`wgpu::Buffer::unmap`] can
+    /// be mapped again, but `wgpu` isn't asked to map a buffer which is still mapped.
+    ///
+    /// If the future is dropped or [cancelled](WgpuFuture::cancel) without its result being taken,
+    /// the buffer is unmapped as soon as the mapping completes, rather than being left mapped with
+    /// nothing knowing about it. The same goes for a mapping which completes after the future has
+    /// already failed, for example with [`Error::Elapsed`]. A
+    /// [detached](WgpuFuture::detach) mapping is left mapped.
+    #[track_caller]
+    pub fn map_async(&self, mode: wgpu::MapMode) -> WgpuFuture<()> {
+        self.map_async_holding(mode, ())
+    }
+
+    /// As [`AsyncBufferSlice::map_async`], but holds on to `guard` until the mapping completes.
+    #[track_caller]
+    pub(crate) fn map_async_holding<G>(&self, mode: wgpu::MapMode, guard: G) -> WgpuFuture<()>
+    where
+        G: Send + 'static,
+    {
+        let buffer = Arc::clone(self.buffer);
         self.device
-            .do_async(|callback| self.buffer_slice.map_async(mode, callback))
+            .do_async_fallible(OperationKind::Map, |callback| {
+                // `wgpu` panics when mapping a mapped buffer, so only map one we know isn't
+                buffer.sync_map_state();
+                if buffer
+                    .map_state
+                    .compare_exchange(UNMAPPED, MAPPING, Ordering::AcqRel, Ordering::Acquire)
+                    .is_err()
+                {
+                    callback.complete(Err(Error::AlreadyMapped));
+                    return;
+                }
+
+                self.buffer_slice.map_async(mode, move |res| {
+                    let mapped = res.is_ok();
+                    buffer
+                        .map_state
+                        .store(if mapped { MAPPED } else { UNMAPPED }, Ordering::Release);
+                    if !callback.complete(res.map_err(Error::from)) && mapped {
+                        buffer.unmap();
+                    }
+                    drop(guard);
+                })
+            })
+            .unmap_on_cancel(Arc::clone(self.buffer))
+    }
+
+    /// The buffer this is a slice of.
+    pub(crate) fn buffer(&self) -> &Arc<MappableBuffer> {
+        self.buffer
     }
 }
 impl<'a> Deref for AsyncBufferSlice<'a> {
@@ -100,3 +336,67 @@ where
         self.deref_mut().as_mut()
     }
 }
+
+/// The bytes of a slice mapped for reading, given by [`AsyncBuffer::map_read`]. Unmaps the
+/// buffer when dropped.
+#[derive(Debug)]
+pub struct MappedRead<'a> {
+    buffer: &'a Arc<MappableBuffer>,
+    /// Only taken on drop, since the view must be dropped before the buffer is unmapped.
+    view: Option<wgpu::BufferView<'a>>,
+}
+impl Deref for MappedRead<'_> {
+    type Target = [u8];
+
+    fn deref(&self) -> &Self::Target {
+        self.view.as_ref().expect("view is only taken on drop")
+    }
+}
+impl AsRef<[u8]> for MappedRead<'_> {
+    fn as_ref(&self) -> &[u8] {
+        self
+    }
+}
+impl Drop for MappedRead<'_> {
+    fn drop(&mut self) {
+        drop(self.view.take());
+        self.buffer.unmap();
+    }
+}
+
+/// The bytes of a slice mapped for writing, given by [`AsyncBuffer::map_write`]. Unmaps the
+/// buffer when dropped.
+#[derive(Debug)]
+pub struct MappedWrite<'a> {
+    buffer: &'a Arc<MappableBuffer>,
+    /// Only taken on drop, since the view must be dropped before the buffer is unmapped.
+    view: Option<wgpu::BufferViewMut<'a>>,
+}
+impl Deref for MappedWrite<'_> {
+    type Target = [u8];
+
+    fn deref(&self) -> &Self::Target {
+        self.view.as_ref().expect("view is only taken on drop")
+    }
+}
+impl DerefMut for MappedWrite<'_> {
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        self.view.as_mut().expect("view is only taken on drop")
+    }
+}
+impl AsRef<[u8]> for MappedWrite<'_> {
+    fn as_ref(&self) -> &[u8] {
+        self
+    }
+}
+impl AsMut<[u8]> for MappedWrite<'_> {
+    fn as_mut(&mut self) -> &mut [u8] {
+        self
+    }
+}
+impl Drop for MappedWrite<'_> {
+    fn drop(&mut self) {
+        drop(self.view.take());
+        self.buffer.unmap();
+    }
+}
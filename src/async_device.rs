@@ -1,34 +1,120 @@
+use crate::async_buffer::MappableBuffer;
+#[cfg(not(target_arch = "wasm32"))]
+use crate::builder::DeviceOptions;
+use crate::pending::{OperationKind, PendingOperationInfo};
+#[cfg(not(target_arch = "wasm32"))]
+use crate::poll::{PollDriver, PollLoop, Poller, Sleep};
+use crate::registry::{self, DeviceShared};
+use crate::texture_upload::{self, MipSource, UploadRegion};
+use crate::wgpu_future::WgpuCallback;
 use crate::AsyncBuffer;
+use crate::AsyncDeviceBuilder;
+use crate::AsyncQueue;
+#[cfg(feature = "bytemuck")]
+use crate::AsyncTypedBuffer;
+use crate::Callback;
+use crate::Error;
+use crate::Scope;
 use crate::WgpuFuture;
+use std::future::Future;
 use std::ops::Deref;
 use std::sync::Arc;
+#[cfg(not(target_arch = "wasm32"))]
+use std::time::{Duration, Instant};
 use wgpu::util::{BufferInitDescriptor, DeviceExt};
-use wgpu::Device;
+use wgpu::{Device, WasmNotSend};
 
 /// A wrapper around a [`wgpu::Device`] which shadows some methods to allow for callback-and-poll
 /// methods to be made async.
+///
+/// Every `AsyncDevice` wrapping the same [`wgpu::Device`] shares the same poll loop.
 #[derive(Clone, Debug)]
 pub struct AsyncDevice {
-    // PollLoop must be dropped before device to ensure device is not dropped on poll thread
-    #[cfg(not(target_arch = "wasm32"))]
-    pub(crate) poll_loop: Arc<crate::wgpu_future::PollLoop>,
-
+    // Shared state holds the poller, which must be dropped before device to ensure device is not dropped on poll thread
+    pub(crate) shared: Arc<DeviceShared>,
     device: Arc<Device>,
 }
 
 impl AsyncDevice {
     pub(crate) fn new(device: Arc<Device>) -> Self {
-        Self {
-            #[cfg(not(target_arch = "wasm32"))]
-            poll_loop: Arc::new(crate::wgpu_future::PollLoop::new(Arc::downgrade(&device))),
-            device,
+        Self::get_or_new(device, AsyncDeviceBuilder::new())
+            .expect("failed to spawn poll thread")
+            .0
+    }
+
+    /// Creates a device configured by a builder, failing with [`std::io::ErrorKind::AlreadyExists`]
+    /// if the device has already been wrapped, since the builder would then be ignored.
+    pub(crate) fn new_with_builder(
+        device: Arc<Device>,
+        builder: AsyncDeviceBuilder,
+    ) -> std::io::Result<Self> {
+        match Self::get_or_new(device, builder)? {
+            (device, true) => Ok(device),
+            (_, false) => Err(std::io::Error::new(
+                std::io::ErrorKind::AlreadyExists,
+                "device is already wrapped",
+            )),
         }
     }
 
+    /// Gets the wrapper of a device which has already been wrapped, or creates one configured by a
+    /// builder, giving whether it was created.
+    fn get_or_new(
+        device: Arc<Device>,
+        builder: AsyncDeviceBuilder,
+    ) -> std::io::Result<(Self, bool)> {
+        let mut created = false;
+        let shared = registry::get_or_register(&device, || {
+            created = true;
+            DeviceShared::new(
+                &device,
+                builder.options,
+                #[cfg(not(target_arch = "wasm32"))]
+                |device, pending| {
+                    PollLoop::new(device, pending, builder.thread).map(Poller::Thread)
+                },
+            )
+        })?;
+
+        Ok((Self { shared, device }, created))
+    }
+
+    /// Creates a device which is polled by the returned [`PollDriver`], rather than by a thread.
+    ///
+    /// Gives `None` if the device has already been wrapped, since it is then already being polled.
+    #[cfg(not(target_arch = "wasm32"))]
+    pub(crate) fn new_with_driver(device: Arc<Device>, sleep: Sleep) -> Option<(Self, PollDriver)> {
+        let mut driver = None;
+        let shared = registry::get_or_register(&device, || {
+            DeviceShared::new(&device, DeviceOptions::default(), |device, pending| {
+                let (new_driver, handle) = PollDriver::new(device, pending, sleep);
+                driver = Some(new_driver);
+                Ok(Poller::Driver(handle))
+            })
+        })
+        .expect("creating a poll driver can't fail");
+
+        Some((Self { shared, device }, driver?))
+    }
+
+    /// Gets the [`AsyncDevice`] already wrapping a device, if there is one, without creating a new poll loop.
+    pub fn from_existing(device: &Arc<Device>) -> Option<Self> {
+        let shared = registry::get(device)?;
+
+        Some(Self {
+            shared,
+            device: Arc::clone(device),
+        })
+    }
+
     /// Converts a callback-and-poll `wgpu` method pair into a future.
     ///
     /// The function given is called immediately, usually initiating work on the GPU immediately, however
-    /// the device is only polled once the future is awaited.
+    /// the device is only polled once the future is awaited, unless the future is made
+    /// [eager](WgpuFuture::eager) or the device was wrapped with [`AsyncDeviceBuilder::eager`].
+    ///
+    /// The callback given to `f` is boxed, making one allocation per call. See
+    /// [`AsyncDevice::do_async_unboxed`] to avoid it.
     ///
     /// # Example
     ///
@@ -42,21 +128,241 @@ impl AsyncDevice {
     /// let result = future.await;
     /// # };
     /// ```
+    #[track_caller]
     pub fn do_async<F, R>(&self, f: F) -> WgpuFuture<R>
     where
         F: FnOnce(Box<dyn FnOnce(R) + Send>),
         R: Send + 'static,
     {
-        let future = WgpuFuture::new(self.clone());
-        f(future.callback());
-        future
+        self.do_async_fallible(OperationKind::Custom, |callback: WgpuCallback<R>| {
+            f(Box::new(move |res| {
+                callback.complete(Ok(res));
+            }))
+        })
+    }
+
+    /// As [`AsyncDevice::do_async`], but without boxing the callback, so that no allocation is made
+    /// beyond what `wgpu` itself makes.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// # let _ = stringify! {
+    /// let future = device.do_async_unboxed(|callback|
+    ///     buffer_slice.map_async(mode, move |res| callback.complete(res))
+    /// );
+    /// let result = future.await;
+    /// # };
+    /// ```
+    #[track_caller]
+    pub fn do_async_unboxed<F, R>(&self, f: F) -> WgpuFuture<R>
+    where
+        F: FnOnce(Callback<R>),
+        R: Send + 'static,
+    {
+        self.do_async_fallible(OperationKind::Custom, |callback: WgpuCallback<R>| {
+            f(Callback::new(callback))
+        })
+    }
+
+    /// Converts a callback-and-poll `wgpu` method pair into a fire-and-forget task, calling `then` with
+    /// the result once the operation completes.
+    ///
+    /// The device is polled until the operation completes, without anything needing to await it.
+    /// `then` is called on whichever thread completes the operation, which is usually the thread
+    /// polling the device, so should be quick. See also [`WgpuFuture::detach`].
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// # let _ = stringify! {
+    /// device.on_complete(
+    ///     |callback| queue.on_submitted_work_done(move || callback(())),
+    ///     move |_| drop(staging_buffer),
+    /// );
+    /// # };
+    /// ```
+    #[track_caller]
+    pub fn on_complete<F, R>(&self, f: F, then: impl FnOnce(Result<R, Error>) + Send + 'static)
+    where
+        F: FnOnce(Box<dyn FnOnce(R) + Send>),
+        R: Send + 'static,
+    {
+        self.do_async(f).detach_with(then)
+    }
+
+    /// As [`AsyncDevice::do_async`], but the operation given can itself resolve the future with an error.
+    #[track_caller]
+    pub(crate) fn do_async_fallible<F, R>(&self, kind: OperationKind, f: F) -> WgpuFuture<R>
+    where
+        F: FnOnce(WgpuCallback<R>),
+        R: Send + 'static,
+    {
+        let (future, callback) = WgpuFuture::new(self.clone(), kind);
+        if let Some(callback) = callback {
+            f(callback);
+        }
+
+        if self.shared.eager {
+            future.eager()
+        } else {
+            future
+        }
+    }
+
+    /// Runs the future given by `f`, then waits for every operation started through the [`Scope`]
+    /// it is given, in the way that [`std::thread::scope`] waits for every thread it spawns.
+    ///
+    /// The device is polled until these operations complete, even if their futures have been
+    /// dropped or [detached](WgpuFuture::detach), so no operation started within the scope is left
+    /// in flight once it returns.
+    ///
+    /// Fails with [`Error::LeftMapped`] if a buffer mapped using [`Scope::map_async`] is still
+    /// mapped at the end of the scope, once every operation has completed.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// # let _ = stringify! {
+    /// let data = device
+    ///     .scope(|s| async move {
+    ///         let slice = buffer.slice(..);
+    ///         s.map_async(&slice, wgpu::MapMode::Read).await?;
+    ///         let data = slice.get_mapped_range().to_vec();
+    ///         buffer.unmap();
+    ///         Ok(data)
+    ///     })
+    ///     .await??;
+    /// # };
+    /// ```
+    pub async fn scope<F, Fut, T>(&self, f: F) -> Result<T, Error>
+    where
+        F: FnOnce(Scope) -> Fut,
+        Fut: Future<Output = T>,
+    {
+        let scope = Scope::new(self.clone());
+        let res = f(scope.clone()).await;
+        scope.finish().await?;
+        Ok(res)
+    }
+
+    /// Runs `f` within an error scope, so that a validation error it causes is given by the future
+    /// returned as [`Error::Validation`], rather than being passed to the device's uncaptured error
+    /// handler, which panics by default.
+    ///
+    /// Error scopes belong to the device rather than to a thread, so validating on many threads at
+    /// once may attribute an error to the wrong call.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// # let _ = stringify! {
+    /// let buffer = device
+    ///     .validate(|device| device.create_buffer(&descriptor))
+    ///     .await?;
+    /// # };
+    /// ```
+    pub fn validate<F, R>(&self, f: F) -> impl Future<Output = Result<R, Error>> + WasmNotSend
+    where
+        F: FnOnce(&Self) -> R,
+        R: WasmNotSend,
+    {
+        self.device.push_error_scope(wgpu::ErrorFilter::Validation);
+        let res = f(self);
+        let error = self.device.pop_error_scope();
+
+        async move {
+            match error.await {
+                Some(err) => Err(Error::from(err)),
+                None => Ok(res),
+            }
+        }
+    }
+
+    /// Shuts down this device, and every other [`AsyncDevice`] wrapping the same [`wgpu::Device`].
+    ///
+    /// Once called, any new future created for the device resolves immediately with
+    /// [`Error::ShutDown`], and the device is no longer given by [`AsyncDevice::from_existing`], so
+    /// wrapping it again starts afresh. The future returned waits for every outstanding operation to
+    /// either complete or reach its deadline, then stops polling the device, waiting for the poll
+    /// thread to finish if there is one, without blocking the executor. Operations without a deadline
+    /// which never complete will cause this to wait forever, see [`AsyncDevice::shutdown_timeout`].
+    pub async fn shutdown(&self) -> ShutdownReport {
+        let pending = self.shared.pending.close(Error::ShutDown);
+        registry::unregister(&self.shared);
+        #[cfg(not(target_arch = "wasm32"))]
+        let expired_before = self.shared.pending.expired_count();
+
+        {
+            // Keep polling while there are outstanding operations, even if nothing is awaiting them
+            #[cfg(not(target_arch = "wasm32"))]
+            let _poll_token = self.shared.poller.start_polling();
+
+            std::future::poll_fn(|cx| self.shared.pending.poll_drained(cx)).await;
+        }
+
+        #[cfg(not(target_arch = "wasm32"))]
+        let timed_out = self.shared.pending.expired_count() - expired_before;
+        #[cfg(target_arch = "wasm32")]
+        let timed_out = 0;
+
+        #[cfg(not(target_arch = "wasm32"))]
+        {
+            self.shared.poller.stop();
+            std::future::poll_fn(|cx| self.shared.poller.poll_stopped(cx)).await;
+        }
+
+        ShutdownReport {
+            pending,
+            timed_out,
+            poll_thread_panicked: matches!(self.poll_health(), PollHealth::Panicked(_)),
+        }
+    }
+
+    /// Lists every operation on the device which has been started but hasn't completed, oldest
+    /// first, including those whose futures have been dropped without the operation completing.
+    ///
+    /// In debug builds, these are also logged as a warning, using the `log` crate, if the last
+    /// [`AsyncDevice`] wrapping the device is dropped while any are outstanding, to help find
+    /// forgotten futures. Since every
+    /// [`WgpuFuture`] holds the device, only operations whose futures have been dropped or
+    /// [detached](WgpuFuture::detach) can be outstanding then. A future which is never dropped, for
+    /// example because it was leaked with [`std::mem::forget`], keeps the device alive, so is never
+    /// reported.
+    pub fn pending_operations(&self) -> Vec<PendingOperationInfo> {
+        self.shared.pending.operations()
+    }
+
+    /// Checks whether the device is still being polled.
+    ///
+    /// If polling panicked, every operation that was pending resolves with
+    /// [`Error::PollPanicked`], as does every operation started afterwards. On the web
+    /// the device is never polled, so this is always [`PollHealth::Healthy`].
+    pub fn poll_health(&self) -> PollHealth {
+        #[cfg(not(target_arch = "wasm32"))]
+        return self.shared.poller.health();
+        #[cfg(target_arch = "wasm32")]
+        return PollHealth::Healthy;
+    }
+
+    /// As [`AsyncDevice::shutdown`], but any operation still outstanding after the given grace period
+    /// is failed with [`Error::Elapsed`] rather than waited for.
+    #[cfg(not(target_arch = "wasm32"))]
+    pub async fn shutdown_timeout(&self, grace: Duration) -> ShutdownReport {
+        self.shared.pending.close(Error::ShutDown);
+        self.shared.pending.limit_deadlines(Instant::now() + grace);
+
+        self.shutdown().await
     }
 
     /// Creates an [`AsyncBuffer`].
     pub fn create_buffer(&self, desc: &wgpu::BufferDescriptor) -> AsyncBuffer {
         AsyncBuffer {
             device: self.clone(),
-            buffer: self.device.create_buffer(desc),
+            buffer: Arc::new(MappableBuffer::new(
+                self.device.create_buffer(desc),
+                desc.mapped_at_creation,
+            )),
         }
     }
 
@@ -64,10 +370,199 @@ impl AsyncDevice {
     pub fn create_buffer_init(&self, desc: &BufferInitDescriptor) -> AsyncBuffer {
         AsyncBuffer {
             device: self.clone(),
-            buffer: self.device.create_buffer_init(desc),
+            // Buffers are only mapped while being initialised
+            buffer: Arc::new(MappableBuffer::new(
+                self.device.create_buffer_init(desc),
+                false,
+            )),
         }
     }
+
+    /// Creates an [`AsyncTypedBuffer`] holding the elements given.
+    ///
+    /// The buffer is padded to meet the size alignment `wgpu` requires, so any length of slice can be
+    /// given. As with [`DeviceExt::create_buffer_init`], unless the buffer has
+    /// [`wgpu::BufferUsages::MAP_WRITE`], its contents are only copied in by the next submission.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `T` is zero sized, or needs an alignment greater than [`wgpu::MAP_ALIGNMENT`].
+    #[cfg(feature = "bytemuck")]
+    pub fn create_buffer_from_slice<T: bytemuck::Pod>(
+        &self,
+        data: &[T],
+        usage: wgpu::BufferUsages,
+    ) -> AsyncTypedBuffer<T> {
+        let buffer = self.create_buffer_init(&BufferInitDescriptor {
+            label: None,
+            contents: bytemuck::cast_slice(data),
+            usage,
+        });
+        AsyncTypedBuffer::new(buffer, data.len())
+    }
+
+    /// Creates a texture holding the data given, resolving once the data has been uploaded and is
+    /// ready to be used on the GPU.
+    ///
+    /// Unlike [`DeviceExt::create_texture_with_data`], the data is uploaded through a mapped staging
+    /// buffer using `queue`, and the future returned waits for the upload to complete. The upload is
+    /// submitted when this is called, rather than when the future is first awaited. Rows of data
+    /// are tightly packed, and the layers and mip levels given are laid out as `mips` describes.
+    /// [`wgpu::TextureUsages::COPY_DST`] is added to the texture's usages, as are
+    /// [`wgpu::TextureUsages::RENDER_ATTACHMENT`] and [`wgpu::TextureUsages::TEXTURE_BINDING`] if the
+    /// mip levels are generated.
+    ///
+    /// Fails with [`Error::Unsupported`] if the texture's format has both depth and stencil
+    /// aspects, or can't otherwise be copied to, or if mip levels are to be generated for a texture
+    /// which [`MipSource::Generate`] doesn't support.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `data` is too short for the texture described.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// # let _ = stringify! {
+    /// let texture = device
+    ///     .create_texture_with_data_async(&queue, &descriptor, &pixels, MipSource::Generate)
+    ///     .await?;
+    /// # };
+    /// ```
+    #[track_caller]
+    pub fn create_texture_with_data_async(
+        &self,
+        queue: &AsyncQueue,
+        desc: &wgpu::TextureDescriptor<'_>,
+        data: &[u8],
+        mips: MipSource,
+    ) -> impl Future<Output = Result<wgpu::Texture, Error>> {
+        let upload = self.upload_texture(queue, desc, data, mips);
+
+        async move {
+            let (texture, submission) = upload?;
+            submission.await?;
+            Ok(texture)
+        }
+    }
+
+    /// Creates a texture and submits the upload of its data, giving the texture along with the
+    /// submission.
+    #[track_caller]
+    fn upload_texture(
+        &self,
+        queue: &AsyncQueue,
+        desc: &wgpu::TextureDescriptor<'_>,
+        data: &[u8],
+        mips: MipSource,
+    ) -> Result<(wgpu::Texture, WgpuFuture<()>), Error> {
+        let mut desc = desc.clone();
+        desc.usage |= wgpu::TextureUsages::COPY_DST;
+        if mips == MipSource::Generate {
+            texture_upload::check_mip_generation(&desc, self.device.features())?;
+            desc.usage |=
+                wgpu::TextureUsages::RENDER_ATTACHMENT | wgpu::TextureUsages::TEXTURE_BINDING;
+        }
+
+        let (regions, staged_size) = texture_upload::upload_regions(&desc, mips)?;
+        let data_size = regions.iter().map(UploadRegion::data_size).sum::<usize>();
+        assert!(
+            data.len() >= data_size,
+            "texture needs {data_size} bytes of data, but was given {}",
+            data.len()
+        );
+
+        let texture = self.device.create_texture(&desc);
+        let staging = self.device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("wgpu-async texture upload"),
+            size: staged_size,
+            usage: wgpu::BufferUsages::COPY_SRC,
+            mapped_at_creation: true,
+        });
+        {
+            let mut staged = staging.slice(..).get_mapped_range_mut();
+            let mut data = data;
+            for region in &regions {
+                let (region_data, rest) = data.split_at(region.data_size());
+                data = rest;
+
+                let row = usize::try_from(region.bytes_per_row).expect("row is larger than memory");
+                let padded_row = usize::try_from(region.padded_bytes_per_row)
+                    .expect("row is larger than memory");
+                let offset = usize::try_from(region.offset).expect("texture is larger than memory");
+                for (i, row_data) in region_data.chunks(row).enumerate() {
+                    let start = offset + i * padded_row;
+                    staged[start..start + row].copy_from_slice(row_data);
+                }
+            }
+        }
+        staging.unmap();
+
+        let mut encoder = self
+            .device
+            .create_command_encoder(&wgpu::CommandEncoderDescriptor {
+                label: Some("wgpu-async texture upload"),
+            });
+        for region in &regions {
+            encoder.copy_buffer_to_texture(
+                wgpu::ImageCopyBuffer {
+                    buffer: &staging,
+                    layout: wgpu::ImageDataLayout {
+                        offset: region.offset,
+                        bytes_per_row: Some(region.padded_bytes_per_row),
+                        rows_per_image: Some(region.rows_per_image),
+                    },
+                },
+                wgpu::ImageCopyTexture {
+                    texture: &texture,
+                    mip_level: region.mip_level,
+                    origin: wgpu::Origin3d {
+                        x: 0,
+                        y: 0,
+                        z: region.layer,
+                    },
+                    aspect: wgpu::TextureAspect::All,
+                },
+                region.extent,
+            );
+        }
+        if mips == MipSource::Generate {
+            self.shared
+                .mip_pipelines
+                .encode(&self.device, &mut encoder, &texture);
+        }
+        let submission = queue.submit([encoder.finish()]);
+
+        Ok((texture, submission))
+    }
+}
+
+/// Whether a device is still being polled, given by [`AsyncDevice::poll_health`].
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum PollHealth {
+    /// The device is polled while futures are waiting on it.
+    Healthy,
+    /// Polling stopped because the device was shut down or dropped.
+    Stopped,
+    /// Polling stopped because it panicked, giving the panic message.
+    Panicked(String),
+}
+
+/// What was left to do when [`AsyncDevice::shutdown`] was called.
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[non_exhaustive]
+pub struct ShutdownReport {
+    /// The operations which had been started but not completed when the shutdown began, oldest
+    /// first.
+    pub pending: Vec<PendingOperationInfo>,
+    /// The number of operations which were failed with [`Error::Elapsed`] during the shutdown,
+    /// rather than completing.
+    pub timed_out: usize,
+    /// Whether the poll thread had panicked.
+    pub poll_thread_panicked: bool,
 }
+
 impl Deref for AsyncDevice {
     type Target = wgpu::Device;
 
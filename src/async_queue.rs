@@ -1,6 +1,15 @@
-use crate::{async_device::AsyncDevice, WgpuFuture};
-use std::{ops::Deref, sync::Arc};
-use wgpu::{CommandBuffer, Queue};
+use crate::pending::OperationKind;
+use crate::range::bounded_range;
+use crate::staging_pool::StagingPool;
+#[cfg(feature = "bytemuck")]
+use crate::AsyncTypedBuffer;
+use crate::{async_device::AsyncDevice, AsyncBuffer, Error, WgpuFuture};
+use std::future::Future;
+use std::ops::{Deref, Range, RangeBounds};
+use std::sync::Arc;
+use wgpu::{
+    BufferAddress, CommandBuffer, Queue, COPY_BUFFER_ALIGNMENT, COPY_BYTES_PER_ROW_ALIGNMENT,
+};
 
 /// A wrapper around a [`wgpu::Queue`] which shadows some methods to allow for callback-and-poll
 /// methods to be made async, such as [`AsyncQueue::submit`].
@@ -8,17 +17,26 @@ use wgpu::{CommandBuffer, Queue};
 pub struct AsyncQueue {
     device: AsyncDevice,
     queue: Arc<Queue>,
+    /// Shared between clones of this queue. Not held by the device, since staging buffers hold the
+    /// device.
+    staging: Arc<StagingPool>,
 }
 
 impl AsyncQueue {
     pub(crate) fn new(device: AsyncDevice, queue: Arc<Queue>) -> Self {
-        Self { device, queue }
+        Self {
+            device,
+            queue,
+            staging: Arc::default(),
+        }
     }
 
     /// This is an `async` version of [`wgpu::Queue::submit`].
     ///
     /// Just like [`wgpu::Queue::submit`], a call to this method starts the given work immediately,
     /// however this method returns a future that can be awaited giving the completion of the submitted work.
+    /// The future resolves with an error if the device is lost before the work completes.
+    #[track_caller]
     pub fn submit<I: IntoIterator<Item = CommandBuffer>>(
         &self,
         command_buffers: I,
@@ -27,16 +45,347 @@ impl AsyncQueue {
 
         queue_ref.submit(command_buffers);
 
-        self.device.do_async(move |callback| {
-            queue_ref.on_submitted_work_done(|| callback(()));
+        self.device
+            .do_async_fallible(OperationKind::Submit, move |callback| {
+                queue_ref.on_submitted_work_done(|| {
+                    callback.complete(Ok(()));
+                });
+            })
+    }
+
+    /// Reads back the bytes of `buffer` within the bounds given, by copying them to a staging buffer
+    /// and mapping it.
+    ///
+    /// The buffer must have been created with [`wgpu::BufferUsages::COPY_SRC`]. Copies must start
+    /// and end on a multiple of [`wgpu::COPY_BUFFER_ALIGNMENT`], so a wider range is copied if
+    /// needed, and only the bytes asked for are given. Staging buffers are reused between reads.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the bounds are out of range, or end within the last [`wgpu::COPY_BUFFER_ALIGNMENT`]
+    /// bytes of a buffer whose size isn't a multiple of it, since those bytes can't be copied.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// # let _ = stringify! {
+    /// let bytes = queue.read_buffer(&buffer, ..).await?;
+    /// # };
+    /// ```
+    #[track_caller]
+    pub fn read_buffer<S: RangeBounds<BufferAddress>>(
+        &self,
+        buffer: &wgpu::Buffer,
+        bounds: S,
+    ) -> impl Future<Output = Result<Vec<u8>, Error>> {
+        let bytes = bounded_range(bounds, buffer.size(), "bytes");
+        self.read_buffer_with(buffer, bytes, <[u8]>::to_vec)
+    }
+
+    /// As [`AsyncQueue::read_buffer`], but reads the elements of a typed buffer within the bounds
+    /// given.
+    #[cfg(feature = "bytemuck")]
+    #[track_caller]
+    pub fn read_typed_buffer<T: bytemuck::Pod, S: RangeBounds<usize>>(
+        &self,
+        buffer: &AsyncTypedBuffer<T>,
+        bounds: S,
+    ) -> impl Future<Output = Result<Vec<T>, Error>> {
+        let elements = buffer.elements(bounds);
+        let size = std::mem::size_of::<T>() as BufferAddress;
+        let bytes = elements.start as BufferAddress * size..elements.end as BufferAddress * size;
+
+        self.read_buffer_with(buffer, bytes, move |bytes| {
+            // Copied into a `Vec<T>`, rather than cast, since the bytes may not be aligned for `T`
+            let mut data = vec![T::zeroed(); elements.len()];
+            bytemuck::cast_slice_mut(&mut data).copy_from_slice(bytes);
+            data
         })
     }
 
+    /// Copies the bytes of `buffer` given to a staging buffer, then gives them to `f` once it has
+    /// been mapped.
+    #[track_caller]
+    fn read_buffer_with<R>(
+        &self,
+        buffer: &wgpu::Buffer,
+        bytes: Range<BufferAddress>,
+        f: impl FnOnce(&[u8]) -> R,
+    ) -> impl Future<Output = Result<R, Error>> {
+        let staged = if bytes.is_empty() {
+            None
+        } else {
+            Some(self.stage_buffer(buffer, bytes.clone()))
+        };
+
+        async move {
+            let Some((staged, copied)) = staged else {
+                return Ok(f(&[]));
+            };
+            staged
+                .read(|staged| {
+                    let start = usize::try_from(bytes.start - copied.start)
+                        .expect("read is larger than memory");
+                    let end = usize::try_from(bytes.end - copied.start)
+                        .expect("read is larger than memory");
+                    f(&staged[start..end])
+                })
+                .await
+        }
+    }
+
+    /// Copies a range of `buffer` covering the bytes given to a staging buffer, widened to meet the
+    /// alignment `wgpu` requires of copies, giving the range copied.
+    #[track_caller]
+    fn stage_buffer(
+        &self,
+        buffer: &wgpu::Buffer,
+        bytes: Range<BufferAddress>,
+    ) -> (StagedRead, Range<BufferAddress>) {
+        let copied = bytes.start - bytes.start % COPY_BUFFER_ALIGNMENT
+            ..bytes.end.div_ceil(COPY_BUFFER_ALIGNMENT) * COPY_BUFFER_ALIGNMENT;
+        assert!(
+            copied.end <= buffer.size(),
+            "the last bytes of a buffer whose size isn't a multiple of `wgpu::COPY_BUFFER_ALIGNMENT` can not be read"
+        );
+        let size = copied.end - copied.start;
+
+        let staged = self.stage(size, |encoder, staging| {
+            encoder.copy_buffer_to_buffer(buffer, copied.start, staging, 0, size)
+        });
+        (staged, copied)
+    }
+
+    /// Reads back the bytes of a texture, as given by [`TextureData`].
+    ///
+    /// The texture must have been created with [`wgpu::TextureUsages::COPY_SRC`]. Rows are copied
+    /// padded to [`wgpu::COPY_BYTES_PER_ROW_ALIGNMENT`], as `wgpu` requires, and the padding is
+    /// removed before the data is given. For block compressed formats, each row is a row of
+    /// blocks, and `extent` must be a whole number of blocks, as for any copy.
+    ///
+    /// Fails with [`Error::Unsupported`] if the texture's format can't be copied from as a whole,
+    /// such as [`wgpu::TextureFormat::Depth24Plus`]. Formats with both depth and stencil aspects are
+    /// read one aspect at a time using [`AsyncQueue::read_texture_aspect`].
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// # let _ = stringify! {
+    /// let pixels = queue
+    ///     .read_texture(&texture, 0, wgpu::Origin3d::ZERO, texture.size())
+    ///     .await?
+    ///     .data;
+    /// # };
+    /// ```
+    #[track_caller]
+    pub fn read_texture(
+        &self,
+        texture: &wgpu::Texture,
+        mip_level: u32,
+        origin: wgpu::Origin3d,
+        extent: wgpu::Extent3d,
+    ) -> impl Future<Output = Result<TextureData, Error>> {
+        self.read_texture_aspect(texture, wgpu::TextureAspect::All, mip_level, origin, extent)
+    }
+
+    /// As [`AsyncQueue::read_texture`], but reads only the aspect of the texture given, such as the
+    /// stencil of a depth-stencil texture.
+    ///
+    /// Fails with [`Error::Unsupported`] if the aspect of the texture's format can't be copied
+    /// from, such as the depth of [`wgpu::TextureFormat::Depth24PlusStencil8`].
+    #[track_caller]
+    pub fn read_texture_aspect(
+        &self,
+        texture: &wgpu::Texture,
+        aspect: wgpu::TextureAspect,
+        mip_level: u32,
+        origin: wgpu::Origin3d,
+        extent: wgpu::Extent3d,
+    ) -> impl Future<Output = Result<TextureData, Error>> {
+        let format = texture.format();
+        let staged = match format.block_copy_size(Some(aspect)) {
+            Some(block_size) => {
+                Ok(self.stage_texture(texture, aspect, mip_level, origin, extent, block_size))
+            }
+            None => Err(Error::Unsupported(format!(
+                "the {aspect:?} aspect of {format:?} textures can't be copied from"
+            ))),
+        };
+
+        async move {
+            let staged = staged?;
+            let (bytes_per_row, rows_per_image) = (staged.bytes_per_row, staged.rows_per_image);
+
+            Ok(TextureData {
+                data: staged.read().await?,
+                format: format.aspect_specific_format(aspect).unwrap_or(format),
+                extent,
+                bytes_per_row,
+                rows_per_image,
+            })
+        }
+    }
+
+    /// Copies a region of one aspect of `texture` to a staging buffer, with each row padded as
+    /// `wgpu` requires.
+    #[track_caller]
+    fn stage_texture(
+        &self,
+        texture: &wgpu::Texture,
+        aspect: wgpu::TextureAspect,
+        mip_level: u32,
+        origin: wgpu::Origin3d,
+        extent: wgpu::Extent3d,
+        block_size: u32,
+    ) -> StagedTexture {
+        let (block_width, block_height) = texture.format().block_dimensions();
+
+        let bytes_per_row = extent.width.div_ceil(block_width) * block_size;
+        let padded_bytes_per_row = bytes_per_row.next_multiple_of(COPY_BYTES_PER_ROW_ALIGNMENT);
+        let rows_per_image = extent.height.div_ceil(block_height);
+        let layers = extent.depth_or_array_layers;
+        let size = BufferAddress::from(padded_bytes_per_row)
+            * BufferAddress::from(rows_per_image)
+            * BufferAddress::from(layers);
+
+        let staged = if size == 0 {
+            None
+        } else {
+            Some(self.stage(size, |encoder, staging| {
+                encoder.copy_texture_to_buffer(
+                    wgpu::ImageCopyTexture {
+                        texture,
+                        mip_level,
+                        origin,
+                        aspect,
+                    },
+                    wgpu::ImageCopyBuffer {
+                        buffer: staging,
+                        layout: wgpu::ImageDataLayout {
+                            offset: 0,
+                            bytes_per_row: Some(padded_bytes_per_row),
+                            rows_per_image: Some(rows_per_image),
+                        },
+                    },
+                    extent,
+                )
+            }))
+        };
+
+        StagedTexture {
+            staged,
+            bytes_per_row,
+            padded_bytes_per_row,
+            rows_per_image,
+        }
+    }
+
+    /// Copies `size` bytes to a staging buffer using `copy`, and starts mapping the staging buffer
+    /// to read them back.
+    #[track_caller]
+    fn stage(
+        &self,
+        size: BufferAddress,
+        copy: impl FnOnce(&mut wgpu::CommandEncoder, &wgpu::Buffer),
+    ) -> StagedRead {
+        let staging = self.staging.take(&self.device, size);
+        let mut encoder = self
+            .device
+            .create_command_encoder(&wgpu::CommandEncoderDescriptor {
+                label: Some("wgpu-async readback"),
+            });
+        copy(&mut encoder, &staging);
+        // Mapping waits for the copy, so there's no need to wait for the submission too
+        self.queue.submit([encoder.finish()]);
+
+        let mapping = staging.slice(..size).map_async(wgpu::MapMode::Read);
+        StagedRead {
+            pool: Arc::clone(&self.staging),
+            staging,
+            size,
+            mapping,
+        }
+    }
+
     /// Gets the device associated with this queue.
     pub fn device(&self) -> &AsyncDevice {
         &self.device
     }
 }
+/// A staging buffer being mapped to read back the bytes copied to it, given by
+/// [`AsyncQueue::stage`].
+struct StagedRead {
+    pool: Arc<StagingPool>,
+    staging: AsyncBuffer,
+    size: BufferAddress,
+    mapping: WgpuFuture<()>,
+}
+
+impl StagedRead {
+    /// Gives the staged bytes to `f` once the staging buffer has been mapped, then returns the
+    /// staging buffer to be reused.
+    async fn read<R>(self, f: impl FnOnce(&[u8]) -> R) -> Result<R, Error> {
+        self.mapping.await?;
+
+        let res = f(&self.staging.slice(..self.size).get_mapped_range());
+        self.staging.unmap();
+
+        self.pool.put(self.staging);
+        Ok(res)
+    }
+}
+
+/// A region of a texture being read back, given by [`AsyncQueue::stage_texture`].
+struct StagedTexture {
+    /// `None` if the region is empty, so nothing was copied.
+    staged: Option<StagedRead>,
+    bytes_per_row: u32,
+    /// The bytes in each row of the staging buffer, which are padded as `wgpu` requires.
+    padded_bytes_per_row: u32,
+    rows_per_image: u32,
+}
+
+impl StagedTexture {
+    /// Gives the staged bytes, with the padding removed from each row.
+    async fn read(self) -> Result<Vec<u8>, Error> {
+        let Some(staged) = self.staged else {
+            return Ok(Vec::new());
+        };
+
+        let row = usize::try_from(self.bytes_per_row).expect("row is larger than memory");
+        let padded_row =
+            usize::try_from(self.padded_bytes_per_row).expect("row is larger than memory");
+        staged
+            .read(|staged| {
+                staged
+                    .chunks(padded_row)
+                    .flat_map(|padded| &padded[..row])
+                    .copied()
+                    .collect()
+            })
+            .await
+    }
+}
+
+/// The contents of a region of a texture, given by [`AsyncQueue::read_texture`].
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[non_exhaustive]
+pub struct TextureData {
+    /// The bytes of the region, tightly packed, with rows of each image one after another, then
+    /// images one after another.
+    pub data: Vec<u8>,
+    /// The format of the data. When reading one aspect of a texture, this is the format of that
+    /// aspect, such as [`wgpu::TextureFormat::Stencil8`] for a depth-stencil texture's stencil.
+    pub format: wgpu::TextureFormat,
+    /// The size of the region read.
+    pub extent: wgpu::Extent3d,
+    /// The number of bytes in each row of `data`. For block compressed formats, a row is a row of
+    /// blocks.
+    pub bytes_per_row: u32,
+    /// The number of rows in each image of `data`.
+    pub rows_per_image: u32,
+}
+
 impl Deref for AsyncQueue {
     type Target = wgpu::Queue;
 
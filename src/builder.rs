@@ -0,0 +1,128 @@
+use std::fmt;
+use std::sync::Arc;
+
+#[cfg(not(target_arch = "wasm32"))]
+use crate::poll::PollThreadConfig;
+use crate::{AsyncDevice, AsyncQueue, Dispatcher};
+
+/// The device lost callback forwarded to by the callback which wrapping sets.
+pub(crate) type DeviceLostCallback = Box<dyn Fn(wgpu::DeviceLostReason, String) + Send>;
+
+/// Configures how a device is wrapped, as an alternative to [`wrap`](crate::wrap).
+///
+/// Wrapping a device which has already been wrapped fails, rather than ignoring the configuration
+/// given here. Use [`AsyncDevice::from_existing`] to get the existing wrapper instead.
+///
+/// # Usage
+///
+/// ```ignore
+/// let (async_device, async_queue) = wgpu_async::AsyncDeviceBuilder::new()
+///     .thread_name("gpu-poll")
+///     .stack_size(256 * 1024)
+///     .on_thread_start(|| profiler::register_current_thread())
+///     .wrap(device, queue)?;
+/// ```
+#[derive(Debug, Default)]
+pub struct AsyncDeviceBuilder {
+    #[cfg(not(target_arch = "wasm32"))]
+    pub(crate) thread: PollThreadConfig,
+    pub(crate) options: DeviceOptions,
+}
+
+/// The configuration of a device which applies however the device is polled.
+#[derive(Default)]
+pub(crate) struct DeviceOptions {
+    pub(crate) eager: bool,
+    pub(crate) dispatcher: Option<Arc<dyn Dispatcher>>,
+    pub(crate) on_device_lost: Option<DeviceLostCallback>,
+}
+
+impl fmt::Debug for DeviceOptions {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("DeviceOptions")
+            .field("eager", &self.eager)
+            .field("dispatcher", &self.dispatcher.is_some())
+            .field("on_device_lost", &self.on_device_lost.is_some())
+            .finish()
+    }
+}
+
+impl AsyncDeviceBuilder {
+    /// Creates a builder with the same configuration used by [`wrap`](crate::wrap).
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Sets the name of the poll thread. Defaults to `wgpu-async-poll`.
+    #[cfg(not(target_arch = "wasm32"))]
+    pub fn thread_name(mut self, name: impl Into<String>) -> Self {
+        self.thread.name = name.into();
+        self
+    }
+
+    /// Sets the stack size of the poll thread, in bytes. Defaults to the size used by [`std::thread::spawn`].
+    #[cfg(not(target_arch = "wasm32"))]
+    pub fn stack_size(mut self, size: usize) -> Self {
+        self.thread.stack_size = Some(size);
+        self
+    }
+
+    /// Sets a function to be called on the poll thread before it starts polling, for example to set
+    /// the thread's affinity or priority, or to register the thread with a profiler.
+    #[cfg(not(target_arch = "wasm32"))]
+    pub fn on_thread_start(mut self, f: impl FnOnce() + Send + 'static) -> Self {
+        self.thread.on_start = Some(Box::new(f));
+        self
+    }
+
+    /// Sets whether every future created for the device starts polling the device as soon as it is
+    /// created, as if [`WgpuFuture::eager`](crate::WgpuFuture::eager) were called on it, rather than
+    /// when it is first awaited. Defaults to `false`.
+    ///
+    /// On the web the device is never polled, so this does nothing.
+    pub fn eager(mut self, eager: bool) -> Self {
+        self.options.eager = eager;
+        self
+    }
+
+    /// Hands the work done once an operation completes, such as waking the task awaiting it, to the
+    /// dispatcher given rather than doing it on the thread polling the device. See [`Dispatcher`].
+    pub fn dispatcher(mut self, dispatcher: impl Dispatcher + 'static) -> Self {
+        self.options.dispatcher = Some(Arc::new(dispatcher));
+        self
+    }
+
+    /// Sets a function to be called when the device is lost, after every pending operation has
+    /// failed with [`Error::DeviceLost`](crate::Error::DeviceLost).
+    ///
+    /// Wrapping a device replaces its device lost callback, so use this rather than
+    /// [`wgpu::Device::set_device_lost_callback`] to be told when the device is lost. As with that
+    /// callback, this is also called with [`wgpu::DeviceLostReason::ReplacedCallback`] if the
+    /// callback set by wrapping is later replaced.
+    pub fn on_device_lost(
+        mut self,
+        f: impl Fn(wgpu::DeviceLostReason, String) + Send + 'static,
+    ) -> Self {
+        self.options.on_device_lost = Some(Box::new(f));
+        self
+    }
+
+    /// Takes a regular `wgpu::Device` and `wgpu::Queue` and gives you the corresponding smart
+    /// pointers, [`AsyncDevice`] and [`AsyncQueue`], configured by this builder.
+    ///
+    /// This replaces the device's lost callback, as [`wrap`](crate::wrap) does, forwarding to the
+    /// function given by [`AsyncDeviceBuilder::on_device_lost`].
+    ///
+    /// Fails if the poll thread could not be spawned, or with [`std::io::ErrorKind::AlreadyExists`]
+    /// if the device has already been wrapped, since it is then already configured.
+    pub fn wrap(
+        self,
+        device: Arc<wgpu::Device>,
+        queue: Arc<wgpu::Queue>,
+    ) -> std::io::Result<(AsyncDevice, AsyncQueue)> {
+        let device = AsyncDevice::new_with_builder(device, self)?;
+        let queue = AsyncQueue::new(device.clone(), queue);
+
+        Ok((device, queue))
+    }
+}
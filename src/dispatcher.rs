@@ -0,0 +1,77 @@
+use std::fmt;
+use std::task::Waker;
+
+/// Runs the work done once an operation completes, such as waking the task awaiting the operation,
+/// or calling the function given to [`AsyncDevice::on_complete`](crate::AsyncDevice::on_complete).
+///
+/// By default this work is done on whichever thread completes the operation, which is usually the
+/// thread polling the device, so a slow waker holds up every other completion. Setting a
+/// dispatcher with [`AsyncDeviceBuilder::dispatcher`](crate::AsyncDeviceBuilder::dispatcher) hands
+/// the work over instead, for example to a channel, a thread pool or an async runtime, leaving the
+/// thread polling the device to only collect results.
+///
+/// Code given to `wgpu` directly, such as the closure passed to a callback in
+/// [`AsyncDevice::do_async`](crate::AsyncDevice::do_async), is still run by `wgpu` while polling.
+///
+/// Implemented for any `Fn(Task)`, so that a dispatcher can be given as a closure.
+///
+/// # Usage
+///
+/// ```ignore
+/// let runtime = tokio::runtime::Handle::current();
+/// let (async_device, async_queue) = wgpu_async::AsyncDeviceBuilder::new()
+///     .dispatcher(move |task: wgpu_async::Task| {
+///         runtime.spawn_blocking(move || task.run());
+///     })
+///     .wrap(device, queue)?;
+/// ```
+pub trait Dispatcher: Send + Sync {
+    /// Runs the task given, or arranges for it to be run soon. The task must be run eventually, or
+    /// the operation it completes will never be seen to complete.
+    fn dispatch(&self, task: Task);
+}
+
+impl<F> Dispatcher for F
+where
+    F: Fn(Task) + Send + Sync,
+{
+    fn dispatch(&self, task: Task) {
+        self(task)
+    }
+}
+
+/// A piece of work given to a [`Dispatcher`], to be run with [`Task::run`].
+///
+/// Waking a single task, which is what most completions do, is held without allocating.
+pub struct Task(TaskKind);
+
+enum TaskKind {
+    Wake(Waker),
+    Run(Box<dyn FnOnce() + Send>),
+}
+
+impl Task {
+    /// A task which wakes the waker given.
+    pub(crate) fn wake(waker: Waker) -> Self {
+        Self(TaskKind::Wake(waker))
+    }
+
+    /// A task which calls the function given.
+    pub(crate) fn new(f: impl FnOnce() + Send + 'static) -> Self {
+        Self(TaskKind::Run(Box::new(f)))
+    }
+
+    /// Does the work.
+    pub fn run(self) {
+        match self.0 {
+            TaskKind::Wake(waker) => waker.wake(),
+            TaskKind::Run(f) => f(),
+        }
+    }
+}
+
+impl fmt::Debug for Task {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Task").finish_non_exhaustive()
+    }
+}
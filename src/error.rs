@@ -0,0 +1,99 @@
+use std::fmt;
+
+/// The reasons that an async operation can fail, given by every [`WgpuFuture`](crate::WgpuFuture)
+/// in place of the result of the operation it is waiting on.
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum Error {
+    /// The device was lost or dropped before the operation completed.
+    DeviceLost {
+        /// Why `wgpu` reports the device as lost.
+        reason: wgpu::DeviceLostReason,
+        /// The message given by `wgpu` when the device was lost.
+        message: String,
+    },
+    /// The device was shut down using [`AsyncDevice::shutdown`](crate::AsyncDevice::shutdown), so no
+    /// more operations can be started.
+    ShutDown,
+    /// Polling the device panicked, giving the panic message. No more operations on the device can
+    /// complete.
+    PollPanicked(String),
+    /// The callback given to `wgpu` was dropped without being called, so the operation can never complete.
+    CallbackDropped,
+    /// The deadline given by [`WgpuFuture::with_deadline`](crate::WgpuFuture::with_deadline) or
+    /// [`WgpuFuture::with_timeout`](crate::WgpuFuture::with_timeout) passed before the operation completed.
+    Elapsed,
+    /// A call to [`wgpu::BufferSlice::map_async`] failed.
+    BufferAsync(wgpu::BufferAsyncError),
+    /// The buffer was already mapped, or being mapped, so couldn't be mapped again until it is
+    /// unmapped.
+    AlreadyMapped,
+    /// A validation error was captured by [`AsyncDevice::validate`](crate::AsyncDevice::validate),
+    /// giving its description.
+    Validation(String),
+    /// `wgpu` ran out of memory, for example while creating a resource.
+    OutOfMemory,
+    /// `wgpu` reported an error not expected by WebGPU, such as a system limit being reached,
+    /// giving its description.
+    Internal(String),
+    /// The future was [cancelled](crate::WgpuFuture::cancel) before its result was taken.
+    Cancelled,
+    /// Buffers mapped using [`Scope::map_async`](crate::Scope::map_async) were still mapped at the
+    /// end of their [`AsyncDevice::scope`](crate::AsyncDevice::scope), giving how many.
+    LeftMapped(usize),
+    /// The operation can't be done with the resource described, such as generating mip levels for
+    /// a format which can't be rendered to, giving the reason.
+    Unsupported(String),
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::DeviceLost { reason, message } => {
+                write!(f, "device lost ({reason:?}): {message}")
+            }
+            Self::ShutDown => write!(f, "device was shut down"),
+            Self::PollPanicked(message) => write!(f, "polling the device panicked: {message}"),
+            Self::CallbackDropped => write!(f, "callback was dropped without being called"),
+            Self::Elapsed => write!(f, "deadline elapsed before the operation completed"),
+            Self::BufferAsync(err) => write!(f, "{err}"),
+            Self::AlreadyMapped => write!(f, "buffer is already mapped"),
+            Self::Validation(description) => write!(f, "validation error: {description}"),
+            Self::OutOfMemory => write!(f, "out of memory"),
+            Self::Internal(description) => write!(f, "internal error: {description}"),
+            Self::Cancelled => write!(f, "operation was cancelled"),
+            Self::LeftMapped(count) => {
+                write!(
+                    f,
+                    "{count} buffer(s) mapped within the scope were left mapped"
+                )
+            }
+            Self::Unsupported(reason) => write!(f, "unsupported: {reason}"),
+        }
+    }
+}
+
+impl std::error::Error for Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Self::BufferAsync(err) => Some(err),
+            _ => None,
+        }
+    }
+}
+
+impl From<wgpu::BufferAsyncError> for Error {
+    fn from(err: wgpu::BufferAsyncError) -> Self {
+        Self::BufferAsync(err)
+    }
+}
+
+impl From<wgpu::Error> for Error {
+    fn from(err: wgpu::Error) -> Self {
+        match err {
+            wgpu::Error::Validation { description, .. } => Self::Validation(description),
+            wgpu::Error::OutOfMemory { .. } => Self::OutOfMemory,
+            wgpu::Error::Internal { description, .. } => Self::Internal(description),
+        }
+    }
+}
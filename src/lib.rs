@@ -20,19 +20,69 @@
 mod async_buffer;
 mod async_device;
 mod async_queue;
+mod builder;
+mod dispatcher;
+mod error;
+#[cfg(all(target_arch = "wasm32", not(target_feature = "atomics")))]
+mod local;
+mod pending;
+#[cfg(not(target_arch = "wasm32"))]
+mod poll;
+mod range;
+mod registry;
+mod scope;
+mod shared_wgpu_future;
+// Both pools keep a bounded number of items, so that a burst of work doesn't hold on to memory
+// forever
+mod slot_pool;
+mod staging_pool;
+mod texture_upload;
+#[cfg(feature = "bytemuck")]
+mod typed_buffer;
 mod wgpu_future;
 
 use std::sync::Arc;
 
 pub use async_buffer::AsyncBuffer;
 pub use async_buffer::AsyncBufferSlice;
+pub use async_buffer::MappedRead;
+pub use async_buffer::MappedWrite;
 pub use async_device::AsyncDevice;
+pub use async_device::PollHealth;
+pub use async_device::ShutdownReport;
 pub use async_queue::AsyncQueue;
+pub use async_queue::TextureData;
+pub use builder::AsyncDeviceBuilder;
+pub use dispatcher::Dispatcher;
+pub use dispatcher::Task;
+pub use error::Error;
+#[cfg(all(target_arch = "wasm32", not(target_feature = "atomics")))]
+pub use local::{
+    LocalAsyncBuffer, LocalAsyncBufferSlice, LocalAsyncDevice, LocalAsyncQueue, LocalWgpuFuture,
+};
+pub use pending::{OperationKind, PendingOperationInfo};
+#[cfg(not(target_arch = "wasm32"))]
+pub use poll::PollDriver;
+pub use scope::Scope;
+pub use shared_wgpu_future::SharedWgpuFuture;
+pub use texture_upload::MipSource;
+#[cfg(feature = "bytemuck")]
+pub use typed_buffer::{AsyncTypedBuffer, AsyncTypedBufferSlice, TypedMappedRead};
+pub use wgpu_future::Callback;
 pub use wgpu_future::WgpuFuture;
 
 /// Takes a regular `wgpu::Device` and `wgpu::Queue` and gives you the corresponding smart
 /// pointers, [`AsyncDevice`] and [`AsyncQueue`].
 ///
+/// Wrapping a device which is already wrapped reuses the existing poll loop, rather than starting another.
+/// To configure the poll loop, use an [`AsyncDeviceBuilder`] instead.
+///
+/// This replaces the device's lost callback, so that futures waiting on the device resolve with
+/// [`Error::DeviceLost`] when the device is lost. A callback set before wrapping is only called once
+/// more, with [`wgpu::DeviceLostReason::ReplacedCallback`], so to also be told when the device is
+/// lost, give a callback to [`AsyncDeviceBuilder::on_device_lost`] instead. Replacing the callback with
+/// [`wgpu::Device::set_device_lost_callback`] afterwards stops futures being resolved this way.
+///
 /// # Usage
 ///
 /// ```
@@ -53,6 +103,7 @@ pub use wgpu_future::WgpuFuture;
 ///             required_features: wgpu::Features::empty(),
 ///             required_limits: adapter.limits(),
 ///             label: None,
+///             memory_hints: wgpu::MemoryHints::default(),
 ///         },
 ///         None,
 ///     )
@@ -73,7 +124,7 @@ pub use wgpu_future::WgpuFuture;
 ///     usage: wgpu::BufferUsages::MAP_READ,
 ///     mapped_at_creation: false,
 /// });
-/// async_buffer.slice(..).map_async(wgpu::MapMode::Read).await; // New await functionality!
+/// async_buffer.slice(..).map_async(wgpu::MapMode::Read).await.unwrap(); // New await functionality!
 /// # })
 /// ```
 pub fn wrap(device: Arc<wgpu::Device>, queue: Arc<wgpu::Queue>) -> (AsyncDevice, AsyncQueue) {
@@ -82,3 +133,63 @@ pub fn wrap(device: Arc<wgpu::Device>, queue: Arc<wgpu::Queue>) -> (AsyncDevice,
 
     (device, queue)
 }
+
+/// As [`wrap`], but rather than spawning a thread to poll the device, gives a [`PollDriver`] future
+/// which polls the device while futures are waiting on it.
+///
+/// The driver must be spawned on an executor, or awaited, for any [`WgpuFuture`] created from the
+/// returned device or queue to make progress. This allows many devices to be polled without
+/// dedicating a thread to each, and no thread is spawned.
+///
+/// Between polls, the driver waits using `sleep`, which should give the executor's sleep future,
+/// such as `tokio::time::sleep`, so that waiting on slow GPU work doesn't keep an executor thread
+/// busy.
+///
+/// Gives `None` if the device has already been wrapped, since it is then already being polled. Use
+/// [`AsyncDevice::from_existing`] to get the existing wrapper instead.
+///
+/// # Usage
+///
+/// ```ignore
+/// let (async_device, async_queue, driver) =
+///     wgpu_async::wrap_with_driver(device, queue, tokio::time::sleep)
+///         .expect("device is already wrapped");
+/// tokio::spawn(driver);
+/// ```
+#[cfg(not(target_arch = "wasm32"))]
+pub fn wrap_with_driver<F, Fut>(
+    device: Arc<wgpu::Device>,
+    queue: Arc<wgpu::Queue>,
+    sleep: F,
+) -> Option<(AsyncDevice, AsyncQueue, PollDriver)>
+where
+    F: FnMut(std::time::Duration) -> Fut + Send + 'static,
+    Fut: std::future::Future<Output = ()> + Send + 'static,
+{
+    let (device, driver) = AsyncDevice::new_with_driver(device, poll::boxed_sleep(sleep))?;
+    let queue = AsyncQueue::new(device.clone(), queue);
+
+    Some((device, queue, driver))
+}
+
+/// As [`wrap`], but for single-threaded web builds, taking `Rc` handles rather than `Arc`s. The
+/// futures given by [`LocalAsyncDevice`] and [`LocalAsyncQueue`] allow results which aren't `Send`.
+///
+/// # Usage
+///
+/// ```ignore
+/// let (device, queue) = (Rc::new(device), Rc::new(queue));
+/// let (async_device, async_queue) = wgpu_async::wrap_local(Rc::clone(&device), Rc::clone(&queue));
+///
+/// async_queue.submit([commands]).await?;
+/// ```
+#[cfg(all(target_arch = "wasm32", not(target_feature = "atomics")))]
+pub fn wrap_local(
+    device: std::rc::Rc<wgpu::Device>,
+    queue: std::rc::Rc<wgpu::Queue>,
+) -> (LocalAsyncDevice, LocalAsyncQueue) {
+    let device = LocalAsyncDevice::new(device);
+    let queue = LocalAsyncQueue::new(device.clone(), queue);
+
+    (device, queue)
+}
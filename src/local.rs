@@ -0,0 +1,303 @@
+//! Single-threaded counterparts of the async wrappers, for the web.
+//!
+//! Without atomics the web only has one thread, so these hold `Rc` handles rather than `Arc`s, and
+//! allow results which aren't `Send`.
+
+use std::cell::RefCell;
+use std::future::Future;
+use std::ops::{Deref, RangeBounds};
+use std::pin::Pin;
+use std::rc::Rc;
+use std::task::{Context, Poll, Waker};
+
+use wgpu::util::{BufferInitDescriptor, DeviceExt};
+use wgpu::{BufferAddress, CommandBuffer};
+
+use crate::Error;
+
+/// Lets a value be moved into a callback which `wgpu` requires to be `Send`.
+struct SingleThreaded<T>(T);
+
+// SAFETY: this module is only built for the web without atomics, where there is only one thread
+unsafe impl<T> Send for SingleThreaded<T> {}
+
+impl<T> SingleThreaded<T> {
+    // Taking `self` makes closures capture the whole wrapper, rather than just the field
+    fn into_inner(self) -> T {
+        self.0
+    }
+}
+
+/// The state that both the future and the callback hold.
+struct LocalState<T> {
+    result: Option<Result<T, Error>>,
+    waker: Option<Waker>,
+}
+
+/// The half of a [`LocalWgpuFuture`] that is moved into the callback given to `wgpu`.
+struct LocalCallback<T> {
+    /// `None` once the future has been resolved.
+    state: Option<Rc<RefCell<LocalState<T>>>>,
+}
+
+impl<T> LocalCallback<T> {
+    fn complete(mut self, result: Result<T, Error>) {
+        self.resolve(result)
+    }
+
+    fn resolve(&mut self, result: Result<T, Error>) {
+        let Some(state) = self.state.take() else {
+            return;
+        };
+
+        let waker = {
+            let mut state = state.borrow_mut();
+            state.result = Some(result);
+            state.waker.take()
+        };
+        if let Some(waker) = waker {
+            waker.wake()
+        }
+    }
+}
+
+impl<T> Drop for LocalCallback<T> {
+    fn drop(&mut self) {
+        self.resolve(Err(Error::CallbackDropped))
+    }
+}
+
+/// The single-threaded counterpart of [`WgpuFuture`](crate::WgpuFuture). Created using
+/// [`LocalAsyncDevice::do_async`].
+pub struct LocalWgpuFuture<T> {
+    device: Rc<wgpu::Device>,
+    state: Rc<RefCell<LocalState<T>>>,
+}
+
+impl<T> Future for LocalWgpuFuture<T> {
+    type Output = Result<T, Error>;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        self.device.poll(wgpu::Maintain::Poll);
+
+        let mut state = self.state.borrow_mut();
+        if let Some(res) = state.result.take() {
+            return Poll::Ready(res);
+        }
+        state.waker = Some(cx.waker().clone());
+
+        Poll::Pending
+    }
+}
+
+/// The single-threaded counterpart of [`AsyncDevice`](crate::AsyncDevice), holding the device in an
+/// `Rc`. Created using [`wrap_local`](crate::wrap_local).
+#[derive(Clone, Debug)]
+pub struct LocalAsyncDevice {
+    device: Rc<wgpu::Device>,
+}
+
+impl LocalAsyncDevice {
+    pub(crate) fn new(device: Rc<wgpu::Device>) -> Self {
+        Self { device }
+    }
+
+    /// Converts a callback-and-poll `wgpu` method pair into a future, as
+    /// [`AsyncDevice::do_async`](crate::AsyncDevice::do_async) does, but allowing results which
+    /// aren't `Send`.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// # let _ = stringify! {
+    /// let future = device.do_async(|callback|
+    ///     buffer_slice.map_async(mode, callback)
+    /// );
+    /// let result = future.await;
+    /// # };
+    /// ```
+    pub fn do_async<F, R>(&self, f: F) -> LocalWgpuFuture<R>
+    where
+        F: FnOnce(Box<dyn FnOnce(R)>),
+        R: 'static,
+    {
+        self.do_async_fallible(|callback| f(Box::new(move |res| callback.complete(Ok(res)))))
+    }
+
+    /// As [`LocalAsyncDevice::do_async`], but the operation given can itself resolve the future
+    /// with an error.
+    fn do_async_fallible<F, R>(&self, f: F) -> LocalWgpuFuture<R>
+    where
+        F: FnOnce(LocalCallback<R>),
+    {
+        let state = Rc::new(RefCell::new(LocalState {
+            result: None,
+            waker: None,
+        }));
+
+        f(LocalCallback {
+            state: Some(Rc::clone(&state)),
+        });
+
+        LocalWgpuFuture {
+            device: Rc::clone(&self.device),
+            state,
+        }
+    }
+
+    /// Creates a [`LocalAsyncBuffer`].
+    pub fn create_buffer(&self, desc: &wgpu::BufferDescriptor) -> LocalAsyncBuffer {
+        LocalAsyncBuffer {
+            device: self.clone(),
+            buffer: self.device.create_buffer(desc),
+        }
+    }
+
+    /// Creates a [`LocalAsyncBuffer`] with data to initialize it.
+    pub fn create_buffer_init(&self, desc: &BufferInitDescriptor) -> LocalAsyncBuffer {
+        LocalAsyncBuffer {
+            device: self.clone(),
+            buffer: self.device.create_buffer_init(desc),
+        }
+    }
+}
+
+impl Deref for LocalAsyncDevice {
+    type Target = wgpu::Device;
+
+    fn deref(&self) -> &Self::Target {
+        &self.device
+    }
+}
+impl<T> AsRef<T> for LocalAsyncDevice
+where
+    T: ?Sized,
+    <LocalAsyncDevice as Deref>::Target: AsRef<T>,
+{
+    fn as_ref(&self) -> &T {
+        self.deref().as_ref()
+    }
+}
+
+/// The single-threaded counterpart of [`AsyncQueue`](crate::AsyncQueue), holding the queue in an
+/// `Rc`. Created using [`wrap_local`](crate::wrap_local).
+#[derive(Clone, Debug)]
+pub struct LocalAsyncQueue {
+    device: LocalAsyncDevice,
+    queue: Rc<wgpu::Queue>,
+}
+
+impl LocalAsyncQueue {
+    pub(crate) fn new(device: LocalAsyncDevice, queue: Rc<wgpu::Queue>) -> Self {
+        Self { device, queue }
+    }
+
+    /// An awaitable version of [`wgpu::Queue::submit`].
+    pub fn submit<I: IntoIterator<Item = CommandBuffer>>(
+        &self,
+        command_buffers: I,
+    ) -> LocalWgpuFuture<()> {
+        self.queue.submit(command_buffers);
+
+        self.device.do_async(|callback| {
+            let callback = SingleThreaded(callback);
+            self.queue
+                .on_submitted_work_done(move || callback.into_inner()(()));
+        })
+    }
+
+    /// Gets the device associated with this queue.
+    pub fn device(&self) -> &LocalAsyncDevice {
+        &self.device
+    }
+}
+
+impl Deref for LocalAsyncQueue {
+    type Target = wgpu::Queue;
+
+    fn deref(&self) -> &Self::Target {
+        &self.queue
+    }
+}
+impl<T> AsRef<T> for LocalAsyncQueue
+where
+    T: ?Sized,
+    <LocalAsyncQueue as Deref>::Target: AsRef<T>,
+{
+    fn as_ref(&self) -> &T {
+        self.deref().as_ref()
+    }
+}
+
+/// The single-threaded counterpart of [`AsyncBuffer`](crate::AsyncBuffer). Created using
+/// [`LocalAsyncDevice::create_buffer`].
+#[derive(Debug)]
+pub struct LocalAsyncBuffer {
+    device: LocalAsyncDevice,
+    buffer: wgpu::Buffer,
+}
+
+impl LocalAsyncBuffer {
+    /// Takes a slice of this buffer, in the same way a call to [`wgpu::Buffer::slice`] would,
+    /// except wraps the result in a [`LocalAsyncBufferSlice`] so that the `map_async` method can be
+    /// awaited.
+    pub fn slice<S: RangeBounds<BufferAddress>>(&self, bounds: S) -> LocalAsyncBufferSlice<'_> {
+        LocalAsyncBufferSlice {
+            device: self.device.clone(),
+            buffer_slice: self.buffer.slice(bounds),
+        }
+    }
+}
+
+impl Deref for LocalAsyncBuffer {
+    type Target = wgpu::Buffer;
+
+    fn deref(&self) -> &Self::Target {
+        &self.buffer
+    }
+}
+impl<T> AsRef<T> for LocalAsyncBuffer
+where
+    T: ?Sized,
+    <LocalAsyncBuffer as Deref>::Target: AsRef<T>,
+{
+    fn as_ref(&self) -> &T {
+        self.deref().as_ref()
+    }
+}
+
+/// The single-threaded counterpart of [`AsyncBufferSlice`](crate::AsyncBufferSlice).
+#[derive(Debug)]
+pub struct LocalAsyncBufferSlice<'a> {
+    device: LocalAsyncDevice,
+    buffer_slice: wgpu::BufferSlice<'a>,
+}
+
+impl LocalAsyncBufferSlice<'_> {
+    /// An awaitable version of [`wgpu::Buffer::map_async`].
+    ///
+    /// A failure to map resolves the future with [`Error::BufferAsync`].
+    pub fn map_async(&self, mode: wgpu::MapMode) -> LocalWgpuFuture<()> {
+        self.device.do_async_fallible(|callback| {
+            self.buffer_slice
+                .map_async(mode, move |res| callback.complete(res.map_err(Error::from)))
+        })
+    }
+}
+
+impl<'a> Deref for LocalAsyncBufferSlice<'a> {
+    type Target = wgpu::BufferSlice<'a>;
+
+    fn deref(&self) -> &Self::Target {
+        &self.buffer_slice
+    }
+}
+impl<'a, T> AsRef<T> for LocalAsyncBufferSlice<'a>
+where
+    T: ?Sized,
+    <LocalAsyncBufferSlice<'a> as Deref>::Target: AsRef<T>,
+{
+    fn as_ref(&self) -> &T {
+        self.deref().as_ref()
+    }
+}
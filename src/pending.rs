@@ -0,0 +1,348 @@
+use std::collections::HashMap;
+use std::fmt;
+use std::panic::Location;
+use std::sync::{Arc, Mutex, MutexGuard, PoisonError, Weak};
+use std::task::{Context, Poll, Waker};
+#[cfg(not(target_arch = "wasm32"))]
+use std::time::{Duration, Instant};
+
+use crate::{Dispatcher, Error, Task};
+
+/// Something waiting on the result of an operation which can be told that the operation failed.
+pub(crate) trait Fail: Send + Sync {
+    /// Fails the operation, waking whatever waits on it using the dispatcher given, if there is one.
+    fn fail(&self, error: Error, dispatcher: Option<&Arc<dyn Dispatcher>>);
+}
+
+/// What an operation does, given by [`AsyncDevice::pending_operations`](crate::AsyncDevice::pending_operations).
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum OperationKind {
+    /// A buffer mapping, started by [`AsyncBufferSlice::map_async`](crate::AsyncBufferSlice::map_async).
+    Map,
+    /// A submission, started by [`AsyncQueue::submit`](crate::AsyncQueue::submit).
+    Submit,
+    /// Any other operation, started by [`AsyncDevice::do_async`](crate::AsyncDevice::do_async) or
+    /// [`AsyncDevice::on_complete`](crate::AsyncDevice::on_complete).
+    Custom,
+}
+
+/// An operation which has been started but hasn't completed, given by
+/// [`AsyncDevice::pending_operations`](crate::AsyncDevice::pending_operations).
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[non_exhaustive]
+pub struct PendingOperationInfo {
+    /// What the operation does.
+    pub kind: OperationKind,
+    /// Where the operation was started.
+    pub location: &'static Location<'static>,
+    /// How long ago the operation was started. Not available on the web, which has no clock
+    /// without JavaScript.
+    #[cfg(not(target_arch = "wasm32"))]
+    pub age: Duration,
+}
+
+impl fmt::Display for PendingOperationInfo {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{:?} started at {}", self.kind, self.location)?;
+        #[cfg(not(target_arch = "wasm32"))]
+        write!(f, ", {:?} ago", self.age)?;
+        Ok(())
+    }
+}
+
+/// The operations started on a device whose callbacks have not yet fired.
+///
+/// Used to resolve every outstanding future with an error when the device goes away, rather
+/// than leaving them pending forever.
+pub(crate) struct PendingOperations {
+    inner: Mutex<PendingOperationsInner>,
+    /// Runs the wakers of these operations, if they shouldn't be run by whatever completes them.
+    dispatcher: Option<Arc<dyn Dispatcher>>,
+}
+
+#[derive(Default)]
+struct PendingOperationsInner {
+    next_id: u64,
+    operations: HashMap<u64, PendingOperation>,
+    /// The number of operations with a deadline, so that we can skip looking for expired operations.
+    #[cfg(not(target_arch = "wasm32"))]
+    deadline_count: usize,
+    /// The number of operations which have been failed because their deadline passed.
+    #[cfg(not(target_arch = "wasm32"))]
+    expired_count: usize,
+    /// Set once the device has been lost or shut down, after which no operation can be started.
+    closed: Option<Error>,
+    /// Woken once there are no operations left.
+    drained_wakers: Vec<Waker>,
+}
+
+impl PendingOperationsInner {
+    fn remove(&mut self, id: u64) -> Option<PendingOperation> {
+        let operation = self.operations.remove(&id)?;
+        #[cfg(not(target_arch = "wasm32"))]
+        if operation.deadline.is_some() {
+            self.deadline_count -= 1;
+        }
+        Some(operation)
+    }
+
+    /// Describes every outstanding operation, oldest first.
+    fn operations(&self) -> Vec<PendingOperationInfo> {
+        #[cfg(not(target_arch = "wasm32"))]
+        let now = Instant::now();
+
+        let mut operations = self.operations.iter().collect::<Vec<_>>();
+        operations.sort_unstable_by_key(|(id, _)| **id);
+        operations
+            .into_iter()
+            .map(|(_, operation)| PendingOperationInfo {
+                kind: operation.kind,
+                location: operation.location,
+                #[cfg(not(target_arch = "wasm32"))]
+                age: now - operation.started,
+            })
+            .collect()
+    }
+
+    /// Takes the wakers to wake, if there are no operations left.
+    fn take_drained_wakers(&mut self) -> Vec<Waker> {
+        if self.operations.is_empty() {
+            std::mem::take(&mut self.drained_wakers)
+        } else {
+            Vec::new()
+        }
+    }
+}
+
+struct PendingOperation {
+    operation: Weak<dyn Fail>,
+    kind: OperationKind,
+    location: &'static Location<'static>,
+    #[cfg(not(target_arch = "wasm32"))]
+    started: Instant,
+    /// When to give up waiting on the operation.
+    #[cfg(not(target_arch = "wasm32"))]
+    deadline: Option<Instant>,
+}
+
+impl PendingOperations {
+    pub(crate) fn new(dispatcher: Option<Arc<dyn Dispatcher>>) -> Self {
+        Self {
+            inner: Mutex::default(),
+            dispatcher,
+        }
+    }
+
+    /// Locks the operations. Nothing which could panic is run while they are locked, so they are
+    /// never left half updated, and a poisoned lock is recovered from rather than panicking.
+    fn lock(&self) -> MutexGuard<'_, PendingOperationsInner> {
+        self.inner.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    /// Where the wakers of these operations should be run, if not by whatever completes them.
+    pub(crate) fn dispatcher(&self) -> Option<&Arc<dyn Dispatcher>> {
+        self.dispatcher.as_ref()
+    }
+
+    /// Wakes the wakers given, using the dispatcher if there is one.
+    fn wake(&self, wakers: Vec<Waker>) {
+        if wakers.is_empty() {
+            return;
+        }
+
+        match self.dispatcher() {
+            Some(dispatcher) => {
+                dispatcher.dispatch(Task::new(move || wakers.into_iter().for_each(Waker::wake)))
+            }
+            None => wakers.into_iter().for_each(Waker::wake),
+        }
+    }
+
+    /// Starts tracking an operation, returning the id to later give to [`PendingOperations::remove`].
+    ///
+    /// If the device has already been lost or shut down then the operation is not tracked, and the
+    /// error is returned instead.
+    pub(crate) fn insert(
+        &self,
+        operation: Weak<dyn Fail>,
+        kind: OperationKind,
+        location: &'static Location<'static>,
+    ) -> Result<u64, Error> {
+        let mut inner = self.lock();
+
+        if let Some(error) = &inner.closed {
+            return Err(error.clone());
+        }
+
+        let id = inner.next_id;
+        inner.next_id += 1;
+        inner.operations.insert(
+            id,
+            PendingOperation {
+                operation,
+                kind,
+                location,
+                #[cfg(not(target_arch = "wasm32"))]
+                started: Instant::now(),
+                #[cfg(not(target_arch = "wasm32"))]
+                deadline: None,
+            },
+        );
+
+        Ok(id)
+    }
+
+    /// Describes every outstanding operation, oldest first.
+    pub(crate) fn operations(&self) -> Vec<PendingOperationInfo> {
+        self.lock().operations()
+    }
+
+    /// Stops tracking an operation, usually because its callback has fired.
+    pub(crate) fn remove(&self, id: u64) {
+        let wakers = {
+            let mut inner = self.lock();
+            inner.remove(id);
+            inner.take_drained_wakers()
+        };
+
+        self.wake(wakers);
+    }
+
+    /// Sets the time after which an operation fails with [`Error::Elapsed`].
+    #[cfg(not(target_arch = "wasm32"))]
+    pub(crate) fn set_deadline(&self, id: u64, deadline: Instant) {
+        let mut inner = self.lock();
+        let PendingOperationsInner {
+            operations,
+            deadline_count,
+            ..
+        } = &mut *inner;
+
+        if let Some(operation) = operations.get_mut(&id) {
+            if operation.deadline.replace(deadline).is_none() {
+                *deadline_count += 1;
+            }
+        }
+    }
+
+    /// Brings the deadline of every outstanding operation forward to at most the given deadline.
+    #[cfg(not(target_arch = "wasm32"))]
+    pub(crate) fn limit_deadlines(&self, deadline: Instant) {
+        let mut inner = self.lock();
+
+        for operation in inner.operations.values_mut() {
+            operation.deadline = Some(operation.deadline.map_or(deadline, |d| d.min(deadline)));
+        }
+        inner.deadline_count = inner.operations.len();
+    }
+
+    /// The number of operations which have been failed because their deadline passed.
+    #[cfg(not(target_arch = "wasm32"))]
+    pub(crate) fn expired_count(&self) -> usize {
+        self.lock().expired_count
+    }
+
+    /// Fails every operation whose deadline is at or before `now`, giving the earliest deadline of
+    /// the operations that remain.
+    #[cfg(not(target_arch = "wasm32"))]
+    pub(crate) fn fail_expired(&self, now: Instant) -> Option<Instant> {
+        let (expired, next_deadline, wakers) = {
+            let mut inner = self.lock();
+            if inner.deadline_count == 0 {
+                return None;
+            }
+
+            let mut expired_ids = Vec::new();
+            let mut next_deadline: Option<Instant> = None;
+            for (id, operation) in &inner.operations {
+                match operation.deadline {
+                    Some(deadline) if deadline <= now => expired_ids.push(*id),
+                    Some(deadline) => {
+                        next_deadline = Some(next_deadline.map_or(deadline, |d| d.min(deadline)))
+                    }
+                    None => {}
+                }
+            }
+            let expired = expired_ids
+                .into_iter()
+                .filter_map(|id| inner.remove(id))
+                .collect::<Vec<_>>();
+            inner.expired_count += expired.len();
+
+            (expired, next_deadline, inner.take_drained_wakers())
+        };
+
+        // Fail outside of the lock, since failing wakes futures
+        for PendingOperation { operation, .. } in expired {
+            if let Some(operation) = operation.upgrade() {
+                operation.fail(Error::Elapsed, self.dispatcher());
+            }
+        }
+        self.wake(wakers);
+
+        next_deadline
+    }
+
+    /// Stops any more operations from starting, giving the error to each that tries. Describes the
+    /// operations still outstanding, oldest first.
+    pub(crate) fn close(&self, error: Error) -> Vec<PendingOperationInfo> {
+        let mut inner = self.lock();
+        if inner.closed.is_none() {
+            inner.closed = Some(error);
+        }
+        inner.operations()
+    }
+
+    /// Fails every outstanding operation, and every operation started from now on, with the given error.
+    pub(crate) fn fail_all(&self, error: Error) {
+        let (operations, wakers) = {
+            let mut inner = self.lock();
+            if inner.closed.is_none() {
+                inner.closed = Some(error.clone());
+            }
+            #[cfg(not(target_arch = "wasm32"))]
+            {
+                inner.deadline_count = 0;
+            }
+            let operations = std::mem::take(&mut inner.operations);
+            (operations, inner.take_drained_wakers())
+        };
+
+        // Fail outside of the lock, since failing wakes futures
+        for PendingOperation { operation, .. } in operations.into_values() {
+            if let Some(operation) = operation.upgrade() {
+                operation.fail(error.clone(), self.dispatcher());
+            }
+        }
+        self.wake(wakers);
+    }
+
+    /// Gives `Poll::Ready` once there are no operations outstanding.
+    pub(crate) fn poll_drained(&self, cx: &mut Context<'_>) -> Poll<()> {
+        let mut inner = self.lock();
+
+        if inner.operations.is_empty() {
+            return Poll::Ready(());
+        }
+
+        if !inner
+            .drained_wakers
+            .iter()
+            .any(|waker| waker.will_wake(cx.waker()))
+        {
+            inner.drained_wakers.push(cx.waker().clone());
+        }
+        Poll::Pending
+    }
+}
+
+impl fmt::Debug for PendingOperations {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let inner = self.lock();
+        f.debug_struct("PendingOperations")
+            .field("count", &inner.operations.len())
+            .field("closed", &inner.closed)
+            .finish_non_exhaustive()
+    }
+}
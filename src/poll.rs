@@ -0,0 +1,572 @@
+use std::fmt;
+use std::future::Future;
+use std::panic::AssertUnwindSafe;
+use std::pin::Pin;
+use std::sync::{
+    atomic::{AtomicBool, AtomicUsize, Ordering},
+    Arc, Mutex, OnceLock, PoisonError, Weak,
+};
+use std::task::{Context, Poll, Waker};
+use std::time::{Duration, Instant};
+
+use atomic_waker::AtomicWaker;
+use wgpu::Maintain;
+
+use crate::pending::PendingOperations;
+use crate::{Error, PollHealth};
+
+/// The error given to pending operations when the device is dropped out from under the poller.
+fn device_dropped() -> Error {
+    Error::DeviceLost {
+        reason: wgpu::DeviceLostReason::Dropped,
+        message: "device was dropped while polling".to_owned(),
+    }
+}
+
+/// Polls the device, catching any panic so that it is given to every pending operation rather
+/// than taking down the poller. Returns `false` if polling panicked.
+fn poll_catching_panic(
+    device: &wgpu::Device,
+    maintain: Maintain,
+    pending: &PendingOperations,
+    panic: &OnceLock<String>,
+) -> bool {
+    let payload = match std::panic::catch_unwind(AssertUnwindSafe(|| device.poll(maintain))) {
+        Ok(_) => return true,
+        Err(payload) => payload,
+    };
+
+    let message = if let Some(message) = payload.downcast_ref::<&str>() {
+        (*message).to_owned()
+    } else if let Some(message) = payload.downcast_ref::<String>() {
+        message.clone()
+    } else {
+        "unknown panic".to_owned()
+    };
+    let _ = panic.set(message.clone());
+    pending.fail_all(Error::PollPanicked(message));
+
+    false
+}
+
+/// The thing responsible for polling a device while futures are waiting on it.
+#[derive(Debug)]
+pub(crate) enum Poller {
+    /// A dedicated thread, see [`PollLoop`].
+    Thread(PollLoop),
+    /// A [`PollDriver`] future, run by the user's executor.
+    Driver(PollDriverHandle),
+}
+
+impl Poller {
+    /// If the device wasn't being polled, start it being polled.
+    pub(crate) fn start_polling(&self) -> PollToken {
+        match self {
+            Self::Thread(poll_loop) => poll_loop.start_polling(),
+            Self::Driver(driver) => driver.start_polling(),
+        }
+    }
+
+    /// Stops polling for good, without waiting for the poll thread to finish.
+    pub(crate) fn stop(&self) {
+        match self {
+            Self::Thread(poll_loop) => poll_loop.stop(),
+            Self::Driver(driver) => driver.stop(),
+        }
+    }
+
+    /// Gives `Poll::Ready` once polling has stopped, joining the poll thread if there is one.
+    pub(crate) fn poll_stopped(&self, cx: &mut Context<'_>) -> Poll<()> {
+        match self {
+            Self::Thread(poll_loop) => poll_loop.poll_stopped(cx),
+            Self::Driver(_) => Poll::Ready(()),
+        }
+    }
+
+    fn panic_and_is_done(&self) -> (&OnceLock<String>, &AtomicBool) {
+        match self {
+            Self::Thread(poll_loop) => (&poll_loop.panic, &poll_loop.is_done),
+            Self::Driver(driver) => (&driver.shared.panic, &driver.shared.is_done),
+        }
+    }
+
+    /// Polls the device from outside of the poller, for example when a future checks if it can avoid
+    /// waiting. A panic while polling stops the poller, just as if the poller had panicked.
+    pub(crate) fn poll_now(
+        &self,
+        device: &wgpu::Device,
+        maintain: Maintain,
+        pending: &PendingOperations,
+    ) {
+        let (panic, is_done) = self.panic_and_is_done();
+        if !poll_catching_panic(device, maintain, pending, panic) {
+            is_done.store(true, Ordering::Release);
+            match self {
+                Self::Thread(poll_loop) => poll_loop.thread.unpark(),
+                Self::Driver(driver) => driver.shared.wake(),
+            }
+        }
+    }
+
+    pub(crate) fn health(&self) -> PollHealth {
+        let (panic, is_done) = self.panic_and_is_done();
+
+        if let Some(message) = panic.get() {
+            PollHealth::Panicked(message.clone())
+        } else if is_done.load(Ordering::Acquire) {
+            PollHealth::Stopped
+        } else {
+            PollHealth::Healthy
+        }
+    }
+}
+
+/// Polls the device while-ever a future says there is something to poll.
+///
+/// This objects corresponds to a thread that parks itself when no futures are
+/// waiting on it, and then calls `device.poll(Maintain::Wait)` repeatedly to block
+/// while-ever it has work that a future is waiting on.
+///
+/// While any pending operation has a deadline, the thread instead calls `device.poll(Maintain::Poll)`
+/// and parks for at most [`DEADLINE_POLL_INTERVAL`] between calls, so that deadlines are noticed
+/// even if the GPU never finishes its work.
+///
+/// If polling panics, the panic is caught and given to every pending operation as
+/// [`Error::PollPanicked`], and the thread stops.
+///
+/// The thread dies when this object is dropped, and when the GPU has finished processing
+/// all active futures.
+#[derive(Debug)]
+pub(crate) struct PollLoop {
+    /// The number of futures still waiting on resolution from the GPU.
+    /// When this is 0, the thread can park itself.
+    has_work: Arc<AtomicUsize>,
+    is_done: Arc<AtomicBool>,
+    /// The message of the panic which stopped the thread, if there was one.
+    panic: Arc<OnceLock<String>>,
+    thread: std::thread::Thread,
+    /// Taken when the thread is joined.
+    handle: Mutex<Option<std::thread::JoinHandle<()>>>,
+    exit: Arc<ThreadExit>,
+}
+
+/// Set by the poll thread as it finishes, so that it can be waited for without blocking.
+#[derive(Debug, Default)]
+struct ThreadExit {
+    exited: AtomicBool,
+    waker: AtomicWaker,
+}
+
+/// Marks the poll thread as finished once dropped, however the thread finishes.
+struct ExitGuard(Arc<ThreadExit>);
+
+impl Drop for ExitGuard {
+    fn drop(&mut self) {
+        self.0.exited.store(true, Ordering::Release);
+        self.0.waker.wake();
+    }
+}
+
+/// The longest that the poll loop waits between polls while there are deadlines to meet.
+pub(crate) const DEADLINE_POLL_INTERVAL: Duration = Duration::from_millis(1);
+
+/// How to spawn the thread of a [`PollLoop`].
+pub(crate) struct PollThreadConfig {
+    pub(crate) name: String,
+    pub(crate) stack_size: Option<usize>,
+    /// Called on the thread before it starts polling.
+    pub(crate) on_start: Option<Box<dyn FnOnce() + Send>>,
+}
+
+impl Default for PollThreadConfig {
+    fn default() -> Self {
+        Self {
+            name: "wgpu-async-poll".to_owned(),
+            stack_size: None,
+            on_start: None,
+        }
+    }
+}
+
+impl fmt::Debug for PollThreadConfig {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("PollThreadConfig")
+            .field("name", &self.name)
+            .field("stack_size", &self.stack_size)
+            .field("on_start", &self.on_start.as_ref().map(|_| ".."))
+            .finish()
+    }
+}
+
+impl PollLoop {
+    pub(crate) fn new(
+        device: Weak<wgpu::Device>,
+        pending: Arc<PendingOperations>,
+        config: PollThreadConfig,
+    ) -> std::io::Result<Self> {
+        let has_work = Arc::new(AtomicUsize::new(0));
+        let is_done = Arc::new(AtomicBool::new(false));
+        let panic = Arc::new(OnceLock::new());
+        let locally_has_work = Arc::clone(&has_work);
+        let locally_is_done = Arc::clone(&is_done);
+        let locally_panic = Arc::clone(&panic);
+        let exit = Arc::new(ThreadExit::default());
+        let exit_guard = ExitGuard(Arc::clone(&exit));
+        let PollThreadConfig {
+            name,
+            stack_size,
+            on_start,
+        } = config;
+        let mut builder = std::thread::Builder::new().name(name);
+        if let Some(stack_size) = stack_size {
+            builder = builder.stack_size(stack_size);
+        }
+
+        let handle = builder.spawn(move || {
+            let _exit_guard = exit_guard;
+            if let Some(on_start) = on_start {
+                on_start();
+            }
+
+            while !locally_is_done.load(Ordering::Acquire) {
+                while locally_has_work.load(Ordering::Acquire) != 0
+                    && !locally_is_done.load(Ordering::Acquire)
+                {
+                    match device.upgrade() {
+                        None => {
+                            // If all other references to the device are dropped, don't keep hold of the device here
+                            locally_is_done.store(true, Ordering::Release);
+                            pending.fail_all(device_dropped());
+                            return;
+                        }
+                        Some(device) => {
+                            let next_deadline = pending.fail_expired(Instant::now());
+                            let maintain = match next_deadline {
+                                None => Maintain::Wait,
+                                Some(_) => Maintain::Poll,
+                            };
+                            if !poll_catching_panic(&device, maintain, &pending, &locally_panic) {
+                                locally_is_done.store(true, Ordering::Release);
+                                return;
+                            }
+                            drop(device);
+
+                            if let Some(next_deadline) = next_deadline {
+                                let timeout = next_deadline
+                                    .saturating_duration_since(Instant::now())
+                                    .min(DEADLINE_POLL_INTERVAL);
+                                std::thread::park_timeout(timeout);
+                            }
+                        }
+                    };
+                }
+
+                std::thread::park();
+            }
+            drop(device);
+        })?;
+
+        Ok(Self {
+            has_work,
+            is_done,
+            panic,
+            thread: handle.thread().clone(),
+            handle: Mutex::new(Some(handle)),
+            exit,
+        })
+    }
+
+    /// If the loop wasn't polling, start it polling.
+    fn start_polling(&self) -> PollToken {
+        let token = PollToken::new(&self.has_work);
+        self.thread.unpark();
+        token
+    }
+
+    /// Tells the thread to stop, without waiting for it to finish.
+    fn stop(&self) {
+        self.is_done.store(true, Ordering::Release);
+        self.thread.unpark();
+    }
+
+    /// Gives `Poll::Ready` once the thread has finished, joining it. As with [`PollLoop::join`],
+    /// the thread isn't waited for from itself.
+    fn poll_stopped(&self, cx: &mut Context<'_>) -> Poll<()> {
+        if std::thread::current().id() != self.thread.id() {
+            self.exit.waker.register(cx.waker());
+            if !self.exit.exited.load(Ordering::Acquire) {
+                return Poll::Pending;
+            }
+        }
+
+        // The thread has finished, so joining it doesn't block
+        let handle = match self.handle.lock() {
+            Ok(mut handle) => handle.take(),
+            Err(poisoned) => poisoned.into_inner().take(),
+        };
+        self.join(handle);
+        Poll::Ready(())
+    }
+
+    /// Waits for the thread to finish, unless this is the thread, which happens when the last device
+    /// is dropped by a callback run while polling. The thread then finishes by itself once that
+    /// callback returns, since it has been told to stop.
+    fn join(&self, handle: Option<std::thread::JoinHandle<()>>) {
+        if std::thread::current().id() == self.thread.id() {
+            return;
+        }
+        if let Some(handle) = handle {
+            // Panics while polling are caught on the thread, and reported through `PollLoop::panic`
+            let _ = handle.join();
+        }
+    }
+}
+
+impl Drop for PollLoop {
+    fn drop(&mut self) {
+        self.is_done.store(true, Ordering::Release);
+        self.thread.unpark();
+
+        // The thread may have already been joined when the device was shut down. Panicking here
+        // could abort the process if we're already unwinding, so never re-raise a panic from the thread.
+        let handle = match self.handle.get_mut() {
+            Ok(handle) => handle.take(),
+            Err(poisoned) => poisoned.into_inner().take(),
+        };
+        self.join(handle);
+    }
+}
+
+/// The state shared between a [`PollDriver`] and the devices it polls for.
+#[derive(Debug, Default)]
+struct PollDriverShared {
+    /// The number of futures still waiting on resolution from the GPU.
+    /// When this is 0, the driver waits to be woken.
+    has_work: Arc<AtomicUsize>,
+    is_done: AtomicBool,
+    /// The message of the panic which stopped the driver, if there was one.
+    panic: OnceLock<String>,
+    waker: Mutex<Option<Waker>>,
+}
+
+impl PollDriverShared {
+    fn wake(&self) {
+        // This is run when a device is dropped, possibly while unwinding, so it mustn't panic
+        let waker = self
+            .waker
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .take();
+        if let Some(waker) = waker {
+            waker.wake()
+        }
+    }
+}
+
+/// The device's side of a [`PollDriver`], which stops the driver when dropped.
+#[derive(Debug)]
+pub(crate) struct PollDriverHandle {
+    shared: Arc<PollDriverShared>,
+}
+
+impl PollDriverHandle {
+    fn start_polling(&self) -> PollToken {
+        let token = PollToken::new(&self.shared.has_work);
+        self.shared.wake();
+        token
+    }
+
+    /// Makes the driver finish, even if there are devices left.
+    fn stop(&self) {
+        self.shared.is_done.store(true, Ordering::Release);
+        self.shared.wake();
+    }
+}
+
+impl Drop for PollDriverHandle {
+    fn drop(&mut self) {
+        self.stop()
+    }
+}
+
+/// The shortest that a [`PollDriver`] waits between polls, used while operations are completing.
+const MIN_DRIVER_POLL_INTERVAL: Duration = Duration::from_micros(50);
+
+/// The longest that a [`PollDriver`] waits between polls, reached after polling many times without
+/// any operation completing.
+const MAX_DRIVER_POLL_INTERVAL: Duration = Duration::from_millis(1);
+
+/// Gives a future which finishes once the duration given has passed, using an executor's timer.
+pub(crate) type Sleep = Box<dyn FnMut(Duration) -> Pin<Box<dyn Future<Output = ()> + Send>> + Send>;
+
+/// Boxes an executor's sleep function, to be given to a [`PollDriver`].
+pub(crate) fn boxed_sleep<F, Fut>(mut sleep: F) -> Sleep
+where
+    F: FnMut(Duration) -> Fut + Send + 'static,
+    Fut: Future<Output = ()> + Send + 'static,
+{
+    Box::new(move |duration| Box::pin(sleep(duration)))
+}
+
+/// A future which polls a device while-ever a future says there is something to poll, in place
+/// of the thread usually spawned for each device. Created using [`wrap_with_driver`](crate::wrap_with_driver).
+///
+/// This future should be spawned on an executor, and runs until every [`AsyncDevice`](crate::AsyncDevice)
+/// created alongside it has been dropped, or the device is shut down. While no futures are waiting on the device it sleeps until
+/// woken, and while futures are waiting it calls `device.poll(Maintain::Poll)`, waiting between
+/// calls using the executor's sleep given to [`wrap_with_driver`](crate::wrap_with_driver). No
+/// thread is spawned to do this.
+///
+/// The wait starts short, and grows up to a millisecond while no operation completes, so that
+/// waiting on slow GPU work doesn't keep an executor thread busy.
+///
+/// If polling panics, the panic is caught and given to every pending operation as
+/// [`Error::PollPanicked`], and the driver finishes.
+#[must_use = "the device is not polled unless the driver is spawned or awaited"]
+pub struct PollDriver {
+    device: Weak<wgpu::Device>,
+    pending: Arc<PendingOperations>,
+    shared: Arc<PollDriverShared>,
+    /// Waits between polls.
+    sleep: Sleep,
+    /// The wait before polling again, if one is in progress.
+    sleeping: Option<Pin<Box<dyn Future<Output = ()> + Send>>>,
+    /// How long to wait before polling again.
+    interval: Duration,
+    /// The number of futures waiting on the device when it was last polled, so that the interval
+    /// can be shortened once it changes.
+    last_work: usize,
+}
+
+impl PollDriver {
+    pub(crate) fn new(
+        device: Weak<wgpu::Device>,
+        pending: Arc<PendingOperations>,
+        sleep: Sleep,
+    ) -> (Self, PollDriverHandle) {
+        let shared = Arc::new(PollDriverShared::default());
+        let handle = PollDriverHandle {
+            shared: Arc::clone(&shared),
+        };
+        let driver = Self {
+            device,
+            pending,
+            shared,
+            sleep,
+            sleeping: None,
+            interval: MIN_DRIVER_POLL_INTERVAL,
+            last_work: 0,
+        };
+
+        (driver, handle)
+    }
+}
+
+impl fmt::Debug for PollDriver {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("PollDriver")
+            .field("device", &self.device)
+            .field("pending", &self.pending)
+            .field("shared", &self.shared)
+            .field("interval", &self.interval)
+            .field("last_work", &self.last_work)
+            .finish_non_exhaustive()
+    }
+}
+
+impl Future for PollDriver {
+    type Output = ();
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let this = self.get_mut();
+
+        // Register before checking for work, so that work started after the check wakes us
+        *this
+            .shared
+            .waker
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner) = Some(cx.waker().clone());
+
+        if this.shared.is_done.load(Ordering::Acquire) {
+            return Poll::Ready(());
+        }
+        let work = this.shared.has_work.load(Ordering::Acquire);
+        if work == 0 {
+            this.last_work = 0;
+            this.sleeping = None;
+            return Poll::Pending;
+        }
+
+        // Finish waiting before polling again
+        if let Some(sleeping) = &mut this.sleeping {
+            if sleeping.as_mut().poll(cx).is_pending() {
+                return Poll::Pending;
+            }
+            this.sleeping = None;
+        }
+
+        match this.device.upgrade() {
+            None => {
+                this.pending.fail_all(device_dropped());
+                return Poll::Ready(());
+            }
+            Some(device) => {
+                if !poll_catching_panic(&device, Maintain::Poll, &this.pending, &this.shared.panic)
+                {
+                    this.shared.is_done.store(true, Ordering::Release);
+                    return Poll::Ready(());
+                }
+            }
+        }
+        let next_deadline = this.pending.fail_expired(Instant::now());
+
+        // Poll often while operations are starting or completing, and back off while they aren't
+        this.interval = if work == this.last_work {
+            (this.interval * 2).min(MAX_DRIVER_POLL_INTERVAL)
+        } else {
+            MIN_DRIVER_POLL_INTERVAL
+        };
+        this.last_work = work;
+
+        // Come back once the interval is up, or once the next deadline passes
+        let mut wait = this.interval;
+        if let Some(next_deadline) = next_deadline {
+            wait = wait.min(next_deadline.saturating_duration_since(Instant::now()));
+        }
+        let mut sleeping = (this.sleep)(wait);
+        if sleeping.as_mut().poll(cx).is_ready() {
+            cx.waker().wake_by_ref();
+        } else {
+            this.sleeping = Some(sleeping);
+        }
+        Poll::Pending
+    }
+}
+
+/// RAII indicating that polling is occurring, while this token is held.
+pub(crate) struct PollToken {
+    work_count: Arc<AtomicUsize>,
+}
+
+impl PollToken {
+    fn new(work_count: &Arc<AtomicUsize>) -> Self {
+        let prev = work_count.fetch_add(1, Ordering::AcqRel);
+        debug_assert!(
+            prev < usize::MAX,
+            "cannot have more than `usize::MAX` outstanding operations on the GPU"
+        );
+        Self {
+            work_count: Arc::clone(work_count),
+        }
+    }
+}
+
+impl Drop for PollToken {
+    fn drop(&mut self) {
+        let prev = self.work_count.fetch_sub(1, Ordering::AcqRel);
+        debug_assert!(
+            prev > 0,
+            "stop_polling was called without calling start_polling"
+        );
+    }
+}
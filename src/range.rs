@@ -0,0 +1,30 @@
+use std::fmt::Display;
+use std::ops::{Add, Bound, Range, RangeBounds};
+
+/// The range within `bounds` of a buffer holding `len` items, such as bytes or elements, named by
+/// `items` in the panic message.
+///
+/// # Panics
+///
+/// Panics if the bounds are out of range.
+pub(crate) fn bounded_range<I>(bounds: impl RangeBounds<I>, len: I, items: &str) -> Range<I>
+where
+    I: Copy + Ord + Display + Add<Output = I> + From<u8>,
+{
+    let start = match bounds.start_bound() {
+        Bound::Included(&start) => start,
+        Bound::Excluded(&start) => start + I::from(1),
+        Bound::Unbounded => I::from(0),
+    };
+    let end = match bounds.end_bound() {
+        Bound::Included(&end) => end + I::from(1),
+        Bound::Excluded(&end) => end,
+        Bound::Unbounded => len,
+    };
+    assert!(
+        start <= end && end <= len,
+        "range {start}..{end} out of bounds for a buffer of {len} {items}"
+    );
+
+    start..end
+}
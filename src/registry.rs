@@ -0,0 +1,153 @@
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex, MutexGuard, OnceLock, PoisonError, Weak};
+
+use crate::builder::DeviceOptions;
+use crate::pending::PendingOperations;
+#[cfg(not(target_arch = "wasm32"))]
+use crate::poll::Poller;
+use crate::slot_pool::SlotPool;
+use crate::texture_upload::MipPipelines;
+use crate::Error;
+
+/// Every device currently wrapped, keyed by the address of the device.
+///
+/// Since a [`DeviceShared`] is only alive while an [`AsyncDevice`](crate::AsyncDevice) holds the
+/// device, an address can't be reused by another device while its entry is alive.
+///
+/// This is locked when a [`DeviceShared`] is dropped, possibly while unwinding, so a poisoned lock
+/// is recovered from rather than panicking. Entries are only inserted or removed whole, so the map
+/// is still consistent if a panic poisoned it.
+fn devices() -> MutexGuard<'static, HashMap<usize, Weak<DeviceShared>>> {
+    static DEVICES: OnceLock<Mutex<HashMap<usize, Weak<DeviceShared>>>> = OnceLock::new();
+    DEVICES
+        .get_or_init(Default::default)
+        .lock()
+        .unwrap_or_else(PoisonError::into_inner)
+}
+
+fn key(device: &Arc<wgpu::Device>) -> usize {
+    Arc::as_ptr(device) as usize
+}
+
+/// The state shared between every [`AsyncDevice`](crate::AsyncDevice) wrapping the same [`wgpu::Device`].
+#[derive(Debug)]
+pub(crate) struct DeviceShared {
+    key: usize,
+
+    #[cfg(not(target_arch = "wasm32"))]
+    pub(crate) poller: Poller,
+    pub(crate) pending: Arc<PendingOperations>,
+    /// Completion slots to reuse, so that starting an operation doesn't allocate.
+    pub(crate) slots: Arc<SlotPool>,
+    /// Whether every future starts polling the device as soon as it is created.
+    pub(crate) eager: bool,
+    /// Used to generate mip levels of uploaded textures.
+    pub(crate) mip_pipelines: MipPipelines,
+}
+
+impl DeviceShared {
+    /// Creates the shared state for a device, setting the device lost callback to fail every
+    /// pending operation, then forward to the callback given by the options.
+    pub(crate) fn new(
+        device: &Arc<wgpu::Device>,
+        options: DeviceOptions,
+        #[cfg(not(target_arch = "wasm32"))] poller: impl FnOnce(
+            Weak<wgpu::Device>,
+            Arc<PendingOperations>,
+        ) -> std::io::Result<Poller>,
+    ) -> std::io::Result<Self> {
+        let pending = Arc::new(PendingOperations::new(options.dispatcher));
+
+        let lost_pending = Arc::downgrade(&pending);
+        let on_device_lost = options.on_device_lost;
+        device.set_device_lost_callback(move |reason, message| {
+            // Replacing this callback doesn't mean that the device was lost
+            if reason != wgpu::DeviceLostReason::ReplacedCallback {
+                if let Some(pending) = lost_pending.upgrade() {
+                    pending.fail_all(Error::DeviceLost {
+                        reason,
+                        message: message.clone(),
+                    });
+                }
+            }
+            if let Some(on_device_lost) = &on_device_lost {
+                on_device_lost(reason, message);
+            }
+        });
+
+        Ok(Self {
+            key: key(device),
+            #[cfg(not(target_arch = "wasm32"))]
+            poller: poller(Arc::downgrade(device), Arc::clone(&pending))?,
+            pending,
+            slots: Arc::default(),
+            eager: options.eager,
+            mip_pipelines: MipPipelines::default(),
+        })
+    }
+}
+
+impl Drop for DeviceShared {
+    fn drop(&mut self) {
+        #[cfg(debug_assertions)]
+        {
+            let operations = self.pending.operations();
+            if !operations.is_empty() && log::log_enabled!(log::Level::Warn) {
+                let listed = operations
+                    .iter()
+                    .map(|operation| format!("\n    {operation}"))
+                    .collect::<String>();
+                log::warn!(
+                    "device dropped with {} operation(s) still pending:{listed}",
+                    operations.len()
+                );
+            }
+        }
+
+        let mut devices = devices();
+
+        // The entry may already have been replaced by a newer wrapper, which shouldn't be removed
+        if devices
+            .get(&self.key)
+            .is_some_and(|entry| entry.strong_count() == 0)
+        {
+            devices.remove(&self.key);
+        }
+    }
+}
+
+/// Gets the shared state of a device which has already been wrapped.
+pub(crate) fn get(device: &Arc<wgpu::Device>) -> Option<Arc<DeviceShared>> {
+    devices().get(&key(device)).and_then(Weak::upgrade)
+}
+
+/// Gets the shared state of a device, creating and registering it if the device hasn't been wrapped.
+pub(crate) fn get_or_register<E>(
+    device: &Arc<wgpu::Device>,
+    create: impl FnOnce() -> Result<DeviceShared, E>,
+) -> Result<Arc<DeviceShared>, E> {
+    let mut devices = devices();
+
+    let key = key(device);
+    if let Some(shared) = devices.get(&key).and_then(Weak::upgrade) {
+        return Ok(shared);
+    }
+
+    let shared = Arc::new(create()?);
+    devices.insert(key, Arc::downgrade(&shared));
+    Ok(shared)
+}
+
+/// Stops a device's shared state being given to new wrappers, so that wrapping the device again
+/// creates new state.
+pub(crate) fn unregister(shared: &Arc<DeviceShared>) {
+    let mut devices = devices();
+
+    // The entry may already have been replaced by a newer wrapper, which shouldn't be removed
+    if devices
+        .get(&shared.key)
+        .is_some_and(|entry| std::ptr::eq(entry.as_ptr(), Arc::as_ptr(shared)))
+    {
+        devices.remove(&shared.key);
+    }
+}
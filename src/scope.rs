@@ -0,0 +1,131 @@
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex, PoisonError, Weak};
+use std::task::{Context, Poll};
+
+use atomic_waker::AtomicWaker;
+
+use crate::async_buffer::MappableBuffer;
+use crate::pending::PendingOperations;
+use crate::{AsyncBufferSlice, AsyncDevice, Error, Task, WgpuFuture};
+
+/// Starts operations whose completion is waited for at the end of an [`AsyncDevice::scope`].
+///
+/// Operations started through a `Scope` after its scope has returned are not waited for.
+#[derive(Clone, Debug)]
+pub struct Scope {
+    device: AsyncDevice,
+    state: Arc<ScopeState>,
+}
+
+#[derive(Debug)]
+struct ScopeState {
+    /// The number of operations started through the scope which haven't completed.
+    outstanding: AtomicUsize,
+    /// Woken once there are no outstanding operations.
+    waker: AtomicWaker,
+    /// Every buffer mapped through the scope, to be checked for being unmapped.
+    mapped: Mutex<Vec<Weak<MappableBuffer>>>,
+    /// The device's operations, whose dispatcher wakes the scope.
+    pending: Arc<PendingOperations>,
+}
+
+/// Counts an operation as outstanding until dropped, which happens once `wgpu` has called or
+/// dropped the operation's callback.
+struct OperationGuard(Arc<ScopeState>);
+
+impl OperationGuard {
+    fn new(state: &Arc<ScopeState>) -> Self {
+        state.outstanding.fetch_add(1, Ordering::AcqRel);
+        Self(Arc::clone(state))
+    }
+}
+
+impl Drop for OperationGuard {
+    fn drop(&mut self) {
+        if self.0.outstanding.fetch_sub(1, Ordering::AcqRel) == 1 {
+            let Some(waker) = self.0.waker.take() else {
+                return;
+            };
+            match self.0.pending.dispatcher() {
+                Some(dispatcher) => dispatcher.dispatch(Task::wake(waker)),
+                None => waker.wake(),
+            }
+        }
+    }
+}
+
+impl Scope {
+    pub(crate) fn new(device: AsyncDevice) -> Self {
+        let state = Arc::new(ScopeState {
+            outstanding: AtomicUsize::new(0),
+            waker: AtomicWaker::new(),
+            mapped: Mutex::default(),
+            pending: Arc::clone(&device.shared.pending),
+        });
+        Self { device, state }
+    }
+
+    /// As [`AsyncDevice::do_async`], but the operation must complete before the scope returns.
+    #[track_caller]
+    pub fn do_async<F, R>(&self, f: F) -> WgpuFuture<R>
+    where
+        F: FnOnce(Box<dyn FnOnce(R) + Send>),
+        R: Send + 'static,
+    {
+        let guard = OperationGuard::new(&self.state);
+        self.device.do_async(move |callback| {
+            f(Box::new(move |res| {
+                callback(res);
+                drop(guard);
+            }))
+        })
+    }
+
+    /// As [`AsyncBufferSlice::map_async`], but the mapping must complete before the scope returns,
+    /// and the buffer must have been unmapped or dropped by then.
+    #[track_caller]
+    pub fn map_async(&self, slice: &AsyncBufferSlice<'_>, mode: wgpu::MapMode) -> WgpuFuture<()> {
+        self.state
+            .mapped
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .push(Arc::downgrade(slice.buffer()));
+
+        slice.map_async_holding(mode, OperationGuard::new(&self.state))
+    }
+
+    fn poll_complete(&self, cx: &mut Context<'_>) -> Poll<()> {
+        self.state.waker.register(cx.waker());
+        if self.state.outstanding.load(Ordering::Acquire) == 0 {
+            Poll::Ready(())
+        } else {
+            Poll::Pending
+        }
+    }
+
+    /// Waits for every operation started through the scope to complete, then checks that every
+    /// buffer mapped through the scope has been unmapped, including through [`wgpu::Buffer::unmap`].
+    pub(crate) async fn finish(&self) -> Result<(), Error> {
+        {
+            // Keep polling until every operation completes, even if nothing is awaiting them
+            #[cfg(not(target_arch = "wasm32"))]
+            let _poll_token = self.device.shared.poller.start_polling();
+
+            std::future::poll_fn(|cx| self.poll_complete(cx)).await;
+        }
+
+        let left_mapped = self
+            .state
+            .mapped
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .iter()
+            .filter_map(Weak::upgrade)
+            .filter(|buffer| buffer.is_mapped())
+            .count();
+        match left_mapped {
+            0 => Ok(()),
+            _ => Err(Error::LeftMapped(left_mapped)),
+        }
+    }
+}
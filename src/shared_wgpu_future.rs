@@ -0,0 +1,117 @@
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::{Arc, Mutex, MutexGuard, PoisonError};
+use std::task::{Context, Poll, Wake, Waker};
+
+use crate::{Error, WgpuFuture};
+
+/// Wakes every task awaiting a [`SharedWgpuFuture`].
+///
+/// Kept apart from the rest of the shared state, since the inner future may be woken while the
+/// shared state is locked to poll it.
+#[derive(Default)]
+struct Notifier {
+    wakers: Mutex<Vec<Waker>>,
+}
+
+impl Notifier {
+    /// Locks the wakers. Nothing which could panic is run while they are locked, so a poisoned
+    /// lock is recovered from rather than panicking.
+    fn lock(&self) -> MutexGuard<'_, Vec<Waker>> {
+        self.wakers.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    fn register(&self, waker: &Waker) {
+        let mut wakers = self.lock();
+        if !wakers.iter().any(|registered| registered.will_wake(waker)) {
+            wakers.push(waker.clone());
+        }
+    }
+}
+
+impl Wake for Notifier {
+    fn wake(self: Arc<Self>) {
+        self.wake_by_ref()
+    }
+
+    fn wake_by_ref(self: &Arc<Self>) {
+        let wakers = std::mem::take(&mut *self.lock());
+        wakers.into_iter().for_each(Waker::wake);
+    }
+}
+
+enum SharedState<T> {
+    Pending(WgpuFuture<T>),
+    Done(Result<T, Error>),
+}
+
+struct Shared<T> {
+    state: Mutex<SharedState<T>>,
+    notifier: Arc<Notifier>,
+    /// Given to the inner future, so that every awaiting task is woken.
+    waker: Waker,
+}
+
+/// A cloneable [`WgpuFuture`], where every clone resolves with a clone of the same result. Created
+/// using [`WgpuFuture::shared`].
+///
+/// However many clones are awaited, the device is only polled on behalf of the one operation.
+pub struct SharedWgpuFuture<T> {
+    shared: Arc<Shared<T>>,
+}
+
+impl<T> SharedWgpuFuture<T> {
+    pub(crate) fn new(future: WgpuFuture<T>) -> Self {
+        let notifier = Arc::new(Notifier::default());
+        Self {
+            shared: Arc::new(Shared {
+                state: Mutex::new(SharedState::Pending(future)),
+                waker: Waker::from(Arc::clone(&notifier)),
+                notifier,
+            }),
+        }
+    }
+}
+
+impl<T> Clone for SharedWgpuFuture<T> {
+    fn clone(&self) -> Self {
+        Self {
+            shared: Arc::clone(&self.shared),
+        }
+    }
+}
+
+impl<T: Clone> Future for SharedWgpuFuture<T> {
+    type Output = Result<T, Error>;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        // Register before polling, so that a completion while polling isn't missed
+        self.shared.notifier.register(cx.waker());
+
+        // The inner future is only ever replaced by its result once it has finished, so the state
+        // is still consistent if a panic while polling poisoned the lock
+        let mut state = self
+            .shared
+            .state
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner);
+        let future = match &mut *state {
+            SharedState::Done(res) => return Poll::Ready(res.clone()),
+            SharedState::Pending(future) => future,
+        };
+
+        let res = match Pin::new(future).poll(&mut Context::from_waker(&self.shared.waker)) {
+            Poll::Ready(res) => res,
+            Poll::Pending => return Poll::Pending,
+        };
+
+        // Dropping the inner future here stops the device being polled for it
+        *state = SharedState::Done(res.clone());
+        drop(state);
+
+        // Every other task awaiting this result can now take it
+        self.shared.notifier.wake_by_ref();
+
+        Poll::Ready(res)
+    }
+}
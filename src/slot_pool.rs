@@ -0,0 +1,55 @@
+use std::any::{Any, TypeId};
+use std::collections::HashMap;
+use std::fmt;
+use std::sync::{Arc, Mutex, MutexGuard, PoisonError};
+
+/// The most slots of each type kept for reuse. A slot is only a few words, so this is well above
+/// the number of operations of one type usually in flight at once.
+const MAX_POOLED_SLOTS: usize = 256;
+
+/// Completion slots whose operations have finished, kept so that starting an operation doesn't
+/// need to allocate.
+///
+/// Slots are pooled by type, since each operation's result type needs a differently sized slot.
+#[derive(Default)]
+pub(crate) struct SlotPool {
+    /// For each slot type `S`, a `Vec<Arc<S>>`.
+    slots: Mutex<HashMap<TypeId, Box<dyn Any + Send>>>,
+}
+
+impl SlotPool {
+    /// Locks the slots. Nothing which could panic is run while they are locked, so a poisoned lock
+    /// is recovered from rather than panicking.
+    fn lock(&self) -> MutexGuard<'_, HashMap<TypeId, Box<dyn Any + Send>>> {
+        self.slots.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    /// Takes a slot to reuse, if there is one.
+    pub(crate) fn take<S: Send + Sync + 'static>(&self) -> Option<Arc<S>> {
+        self.lock()
+            .get_mut(&TypeId::of::<S>())?
+            .downcast_mut::<Vec<Arc<S>>>()?
+            .pop()
+    }
+
+    /// Keeps a slot to be reused. The slot must not be shared, and must have been reset.
+    pub(crate) fn put<S: Send + Sync + 'static>(&self, slot: Arc<S>) {
+        let mut slots = self.lock();
+        let pooled = slots
+            .entry(TypeId::of::<S>())
+            .or_insert_with(|| Box::new(Vec::<Arc<S>>::new()));
+        if let Some(pooled) = pooled.downcast_mut::<Vec<Arc<S>>>() {
+            if pooled.len() < MAX_POOLED_SLOTS {
+                pooled.push(slot);
+            }
+        }
+    }
+}
+
+impl fmt::Debug for SlotPool {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("SlotPool")
+            .field("types", &self.lock().len())
+            .finish_non_exhaustive()
+    }
+}
@@ -0,0 +1,56 @@
+use std::sync::Mutex;
+
+use wgpu::BufferAddress;
+
+use crate::{AsyncBuffer, AsyncDevice};
+
+/// The most staging buffers kept for reuse. Each holds GPU memory at least as large as the read it
+/// was created for, so only about as many are kept as reads are usually in flight at once.
+const MAX_POOLED_BUFFERS: usize = 8;
+
+/// The smallest staging buffer created, so that many small reads can share buffers.
+const MIN_STAGING_SIZE: BufferAddress = 256;
+
+/// Staging buffers which data has been read back through, kept so that reading back doesn't need to
+/// create a buffer every time.
+#[derive(Debug, Default)]
+pub(crate) struct StagingPool {
+    buffers: Mutex<Vec<AsyncBuffer>>,
+}
+
+impl StagingPool {
+    /// Takes a buffer which can be mapped for reading and copied to, with at least `size` bytes.
+    pub(crate) fn take(&self, device: &AsyncDevice, size: BufferAddress) -> AsyncBuffer {
+        // The pool is only a cache, so if it was poisoned we can go without
+        if let Ok(mut buffers) = self.buffers.lock() {
+            let smallest = buffers
+                .iter()
+                .enumerate()
+                .filter(|(_, buffer)| buffer.size() >= size)
+                .min_by_key(|(_, buffer)| buffer.size())
+                .map(|(i, _)| i);
+            if let Some(i) = smallest {
+                return buffers.swap_remove(i);
+            }
+        }
+
+        device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("wgpu-async staging buffer"),
+            // Rounded up so that reads of similar sizes can reuse the buffer
+            size: size.next_power_of_two().max(MIN_STAGING_SIZE),
+            usage: wgpu::BufferUsages::MAP_READ | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        })
+    }
+
+    /// Keeps a buffer to be reused. The buffer must be unmapped.
+    pub(crate) fn put(&self, buffer: AsyncBuffer) {
+        let Ok(mut buffers) = self.buffers.lock() else {
+            return;
+        };
+
+        if buffers.len() < MAX_POOLED_BUFFERS {
+            buffers.push(buffer);
+        }
+    }
+}
@@ -0,0 +1,324 @@
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex, OnceLock, PoisonError};
+
+use wgpu::util::TextureDataOrder;
+use wgpu::{BufferAddress, COPY_BYTES_PER_ROW_ALIGNMENT};
+
+use crate::Error;
+
+/// Where the mip levels of a texture created by
+/// [`AsyncDevice::create_texture_with_data_async`](crate::AsyncDevice::create_texture_with_data_async)
+/// come from.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum MipSource {
+    /// Every mip level of every layer is given in the data, in the order given, as for
+    /// [`wgpu::util::DeviceExt::create_texture_with_data`].
+    Data(TextureDataOrder),
+    /// Only the first mip level of each layer is given in the data, and the rest are generated from
+    /// it by repeatedly downsampling with linear filtering.
+    ///
+    /// Only 2D textures whose format is both renderable and filterable, such as
+    /// [`wgpu::TextureFormat::Rgba8UnormSrgb`], can have their mip levels generated. Uploading
+    /// any other texture fails with [`Error::Unsupported`].
+    Generate,
+}
+
+/// Checks that mip levels can be generated for the texture described on a device with the
+/// features given.
+pub(crate) fn check_mip_generation(
+    desc: &wgpu::TextureDescriptor<'_>,
+    device_features: wgpu::Features,
+) -> Result<(), Error> {
+    if desc.dimension != wgpu::TextureDimension::D2 {
+        return Err(Error::Unsupported(format!(
+            "mip levels can only be generated for 2D textures, not {:?} textures",
+            desc.dimension
+        )));
+    }
+
+    let features = desc.format.guaranteed_format_features(device_features);
+    let renderable = features
+        .allowed_usages
+        .contains(wgpu::TextureUsages::RENDER_ATTACHMENT);
+    let filterable = features
+        .flags
+        .contains(wgpu::TextureFormatFeatureFlags::FILTERABLE);
+    if !renderable || !filterable {
+        return Err(Error::Unsupported(format!(
+            "mip levels can only be generated for formats which are renderable and filterable, \
+             which {:?} isn't",
+            desc.format
+        )));
+    }
+
+    Ok(())
+}
+
+/// Where the data for one mip level of one layer is placed in the staging buffer, and where it is
+/// copied to.
+pub(crate) struct UploadRegion {
+    pub(crate) layer: u32,
+    pub(crate) mip_level: u32,
+    /// The physical size of the mip level, rounded up to whole blocks.
+    pub(crate) extent: wgpu::Extent3d,
+    /// The bytes in each row of the data given.
+    pub(crate) bytes_per_row: u32,
+    /// The bytes in each row of the staging buffer.
+    pub(crate) padded_bytes_per_row: u32,
+    pub(crate) rows_per_image: u32,
+    pub(crate) offset: BufferAddress,
+}
+
+impl UploadRegion {
+    /// The bytes of the data given for this region.
+    pub(crate) fn data_size(&self) -> usize {
+        usize::try_from(
+            u64::from(self.bytes_per_row)
+                * u64::from(self.rows_per_image)
+                * u64::from(self.extent.depth_or_array_layers),
+        )
+        .expect("texture is larger than memory")
+    }
+
+    /// The bytes of the staging buffer used by this region.
+    pub(crate) fn staged_size(&self) -> BufferAddress {
+        BufferAddress::from(self.padded_bytes_per_row)
+            * BufferAddress::from(self.rows_per_image)
+            * BufferAddress::from(self.extent.depth_or_array_layers)
+    }
+}
+
+/// Lays out the regions of the texture which are uploaded, in the order that the data for them is
+/// given, giving the total size of the staging buffer needed.
+pub(crate) fn upload_regions(
+    desc: &wgpu::TextureDescriptor<'_>,
+    mips: MipSource,
+) -> Result<(Vec<UploadRegion>, BufferAddress), Error> {
+    if desc.format.is_combined_depth_stencil_format() {
+        return Err(Error::Unsupported(format!(
+            "{:?} textures have both depth and stencil aspects, which can't be uploaded together",
+            desc.format
+        )));
+    }
+    let Some(block_size) = desc.format.block_copy_size(None) else {
+        return Err(Error::Unsupported(format!(
+            "{:?} textures can't be copied to",
+            desc.format
+        )));
+    };
+    let (block_width, block_height) = desc.format.block_dimensions();
+    let layers = desc.array_layer_count();
+
+    let (order, uploaded_mips) = match mips {
+        MipSource::Data(order) => (order, desc.mip_level_count),
+        MipSource::Generate => (TextureDataOrder::LayerMajor, 1),
+    };
+    let levels = (0..layers).flat_map(|layer| (0..uploaded_mips).map(move |mip| (layer, mip)));
+    let mut levels = levels.collect::<Vec<_>>();
+    if order == TextureDataOrder::MipMajor {
+        levels.sort_by_key(|&(layer, mip)| (mip, layer));
+    }
+
+    let mut offset = 0;
+    let regions = levels
+        .into_iter()
+        .map(|(layer, mip_level)| {
+            let mut size = desc
+                .mip_level_size(mip_level)
+                .expect("mip level is within the texture");
+            // Layers are copied separately
+            if desc.dimension != wgpu::TextureDimension::D3 {
+                size.depth_or_array_layers = 1;
+            }
+            let extent = size.physical_size(desc.format);
+
+            let bytes_per_row = extent.width / block_width * block_size;
+            let region = UploadRegion {
+                layer,
+                mip_level,
+                extent,
+                bytes_per_row,
+                padded_bytes_per_row: bytes_per_row.next_multiple_of(COPY_BYTES_PER_ROW_ALIGNMENT),
+                rows_per_image: extent.height / block_height,
+                offset,
+            };
+            offset += region.staged_size();
+            region
+        })
+        .collect();
+
+    Ok((regions, offset))
+}
+
+/// The objects used to generate mip levels on a device, created the first time they are needed,
+/// then reused.
+#[derive(Debug, Default)]
+pub(crate) struct MipPipelines {
+    shared: OnceLock<MipShared>,
+    /// A pipeline rendering to each format which mip levels have been generated for.
+    pipelines: Mutex<HashMap<wgpu::TextureFormat, Arc<wgpu::RenderPipeline>>>,
+}
+
+/// The objects used to generate mip levels of every format.
+#[derive(Debug)]
+struct MipShared {
+    shader: wgpu::ShaderModule,
+    bind_group_layout: wgpu::BindGroupLayout,
+    pipeline_layout: wgpu::PipelineLayout,
+    sampler: wgpu::Sampler,
+}
+
+impl MipPipelines {
+    fn shared(&self, device: &wgpu::Device) -> &MipShared {
+        self.shared.get_or_init(|| {
+            let shader = device.create_shader_module(wgpu::include_wgsl!("mips.wgsl"));
+            let bind_group_layout =
+                device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+                    label: Some("wgpu-async mip generation"),
+                    entries: &[
+                        wgpu::BindGroupLayoutEntry {
+                            binding: 0,
+                            visibility: wgpu::ShaderStages::FRAGMENT,
+                            ty: wgpu::BindingType::Texture {
+                                sample_type: wgpu::TextureSampleType::Float { filterable: true },
+                                view_dimension: wgpu::TextureViewDimension::D2,
+                                multisampled: false,
+                            },
+                            count: None,
+                        },
+                        wgpu::BindGroupLayoutEntry {
+                            binding: 1,
+                            visibility: wgpu::ShaderStages::FRAGMENT,
+                            ty: wgpu::BindingType::Sampler(wgpu::SamplerBindingType::Filtering),
+                            count: None,
+                        },
+                    ],
+                });
+            let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+                label: Some("wgpu-async mip generation"),
+                bind_group_layouts: &[&bind_group_layout],
+                push_constant_ranges: &[],
+            });
+            let sampler = device.create_sampler(&wgpu::SamplerDescriptor {
+                label: Some("wgpu-async mip generation"),
+                mag_filter: wgpu::FilterMode::Linear,
+                min_filter: wgpu::FilterMode::Linear,
+                ..Default::default()
+            });
+
+            MipShared {
+                shader,
+                bind_group_layout,
+                pipeline_layout,
+                sampler,
+            }
+        })
+    }
+
+    /// The pipeline rendering one mip level of a texture of the format given from the level before.
+    fn pipeline(
+        &self,
+        device: &wgpu::Device,
+        format: wgpu::TextureFormat,
+    ) -> Arc<wgpu::RenderPipeline> {
+        let shared = self.shared(device);
+        let mut pipelines = self
+            .pipelines
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner);
+        let pipeline = pipelines.entry(format).or_insert_with(|| {
+            Arc::new(
+                device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+                    label: Some("wgpu-async mip generation"),
+                    layout: Some(&shared.pipeline_layout),
+                    vertex: wgpu::VertexState {
+                        module: &shared.shader,
+                        entry_point: Some("vs_main"),
+                        compilation_options: Default::default(),
+                        buffers: &[],
+                    },
+                    primitive: wgpu::PrimitiveState::default(),
+                    depth_stencil: None,
+                    multisample: wgpu::MultisampleState::default(),
+                    fragment: Some(wgpu::FragmentState {
+                        module: &shared.shader,
+                        entry_point: Some("fs_main"),
+                        compilation_options: Default::default(),
+                        targets: &[Some(format.into())],
+                    }),
+                    multiview: None,
+                    cache: None,
+                }),
+            )
+        });
+        Arc::clone(pipeline)
+    }
+
+    /// Encodes passes which fill every mip level of a texture after the first by downsampling the
+    /// level before it.
+    pub(crate) fn encode(
+        &self,
+        device: &wgpu::Device,
+        encoder: &mut wgpu::CommandEncoder,
+        texture: &wgpu::Texture,
+    ) {
+        let pipeline = self.pipeline(device, texture.format());
+        let MipShared {
+            bind_group_layout,
+            sampler,
+            ..
+        } = self.shared(device);
+
+        let level_view = |layer, mip_level| {
+            texture.create_view(&wgpu::TextureViewDescriptor {
+                label: Some("wgpu-async mip generation"),
+                dimension: Some(wgpu::TextureViewDimension::D2),
+                base_mip_level: mip_level,
+                mip_level_count: Some(1),
+                base_array_layer: layer,
+                array_layer_count: Some(1),
+                ..Default::default()
+            })
+        };
+
+        for layer in 0..texture.depth_or_array_layers() {
+            for mip_level in 1..texture.mip_level_count() {
+                let source = level_view(layer, mip_level - 1);
+                let target = level_view(layer, mip_level);
+                let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+                    label: Some("wgpu-async mip generation"),
+                    layout: bind_group_layout,
+                    entries: &[
+                        wgpu::BindGroupEntry {
+                            binding: 0,
+                            resource: wgpu::BindingResource::TextureView(&source),
+                        },
+                        wgpu::BindGroupEntry {
+                            binding: 1,
+                            resource: wgpu::BindingResource::Sampler(sampler),
+                        },
+                    ],
+                });
+
+                let mut pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+                    label: Some("wgpu-async mip generation"),
+                    color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+                        view: &target,
+                        resolve_target: None,
+                        ops: wgpu::Operations {
+                            load: wgpu::LoadOp::Clear(wgpu::Color::TRANSPARENT),
+                            store: wgpu::StoreOp::Store,
+                        },
+                    })],
+                    depth_stencil_attachment: None,
+                    timestamp_writes: None,
+                    occlusion_query_set: None,
+                });
+                pass.set_pipeline(&pipeline);
+                pass.set_bind_group(0, &bind_group, &[]);
+                pass.draw(0..3, 0..1);
+            }
+        }
+    }
+}
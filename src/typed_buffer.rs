@@ -0,0 +1,196 @@
+use std::fmt;
+use std::marker::PhantomData;
+use std::ops::{Deref, Range, RangeBounds};
+
+use bytemuck::Pod;
+use wgpu::{BufferAddress, COPY_BUFFER_ALIGNMENT, MAP_ALIGNMENT};
+
+use crate::range::bounded_range;
+use crate::{AsyncBuffer, Error, MappedRead};
+
+/// An [`AsyncBuffer`] holding elements of type `T`, indexed by element rather than by byte. Created
+/// using [`AsyncDevice::create_buffer_from_slice`](crate::AsyncDevice::create_buffer_from_slice).
+///
+/// Derefs to the underlying [`AsyncBuffer`], for anything which still needs bytes.
+pub struct AsyncTypedBuffer<T> {
+    buffer: AsyncBuffer,
+    len: usize,
+    // Only describes the contents of the buffer, so shouldn't affect `Send` or `Sync`
+    _elements: PhantomData<fn() -> T>,
+}
+
+impl<T: Pod> AsyncTypedBuffer<T> {
+    /// Wraps a buffer holding `len` elements.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `T` is zero sized, or needs an alignment greater than [`wgpu::MAP_ALIGNMENT`],
+    /// since mapped views of the buffer couldn't then be given as a `&[T]`.
+    pub(crate) fn new(buffer: AsyncBuffer, len: usize) -> Self {
+        assert!(
+            std::mem::size_of::<T>() != 0,
+            "typed buffers can not hold zero sized elements"
+        );
+        assert!(
+            std::mem::align_of::<T>() as BufferAddress <= MAP_ALIGNMENT,
+            "typed buffers can not hold elements aligned to more than `wgpu::MAP_ALIGNMENT`"
+        );
+
+        Self {
+            buffer,
+            len,
+            _elements: PhantomData,
+        }
+    }
+
+    /// The number of elements in the buffer.
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    /// Whether the buffer holds no elements.
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// Takes a slice of this buffer, indexed by element, in the same way as indexing a slice.
+    ///
+    /// Borrows the buffer mutably, so that while the slice, or a [`TypedMappedRead`] made from it,
+    /// is alive, the buffer can't be mapped again, as with [`AsyncBuffer::map_read`].
+    ///
+    /// # Panics
+    ///
+    /// Panics if the range is out of bounds, or is empty, since `wgpu` doesn't allow empty buffer
+    /// slices.
+    pub fn slice<S: RangeBounds<usize>>(&mut self, bounds: S) -> AsyncTypedBufferSlice<'_, T> {
+        let elements = self.elements(bounds);
+        assert!(!elements.is_empty(), "buffer slices can not be empty");
+
+        AsyncTypedBufferSlice {
+            buffer: &mut self.buffer,
+            elements,
+            _elements: PhantomData,
+        }
+    }
+
+    /// The indices of the elements within the bounds given.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the bounds are out of range.
+    pub(crate) fn elements<S: RangeBounds<usize>>(&self, bounds: S) -> Range<usize> {
+        bounded_range(bounds, self.len, "elements")
+    }
+}
+
+impl<T> Deref for AsyncTypedBuffer<T> {
+    type Target = AsyncBuffer;
+
+    fn deref(&self) -> &Self::Target {
+        &self.buffer
+    }
+}
+impl<T, U> AsRef<U> for AsyncTypedBuffer<T>
+where
+    U: ?Sized,
+    <AsyncTypedBuffer<T> as Deref>::Target: AsRef<U>,
+{
+    fn as_ref(&self) -> &U {
+        self.deref().as_ref()
+    }
+}
+
+impl<T> fmt::Debug for AsyncTypedBuffer<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("AsyncTypedBuffer")
+            .field("buffer", &self.buffer)
+            .field("len", &self.len)
+            .finish()
+    }
+}
+
+/// A range of elements of an [`AsyncTypedBuffer`], which can be mapped to give a `&[T]`.
+pub struct AsyncTypedBufferSlice<'a, T> {
+    buffer: &'a mut AsyncBuffer,
+    elements: Range<usize>,
+    _elements: PhantomData<fn() -> T>,
+}
+
+impl<'a, T: Pod> AsyncTypedBufferSlice<'a, T> {
+    /// The bytes of the buffer holding the elements of this slice.
+    fn bytes(&self) -> Range<BufferAddress> {
+        let size = std::mem::size_of::<T>() as BufferAddress;
+        self.elements.start as BufferAddress * size..self.elements.end as BufferAddress * size
+    }
+
+    /// The bytes of the buffer to map to read this slice, widened to meet the alignment `wgpu`
+    /// requires of mappings.
+    fn mapped_bytes(&self) -> Range<BufferAddress> {
+        let bytes = self.bytes();
+        let start = bytes.start - bytes.start % MAP_ALIGNMENT;
+        // Buffers are always a multiple of `COPY_BUFFER_ALIGNMENT` long, so this stays in bounds
+        let end = bytes.end.div_ceil(COPY_BUFFER_ALIGNMENT) * COPY_BUFFER_ALIGNMENT;
+        start..end
+    }
+
+    /// Maps this slice for reading, giving a view of its elements once the mapping completes.
+    ///
+    /// The buffer must have been created with [`wgpu::BufferUsages::MAP_READ`]. Alignment is dealt
+    /// with for us, by mapping the smallest range that `wgpu` allows which covers the slice. The
+    /// buffer is unmapped when the view is dropped. Fails as
+    /// [`AsyncBuffer::map_read`] does.
+    pub async fn map_read(self) -> Result<TypedMappedRead<'a, T>, Error> {
+        let mapped_bytes = self.mapped_bytes();
+        let bytes = self.bytes();
+        let mapped = self.buffer.map_read(mapped_bytes.clone()).await?;
+
+        let start = usize::try_from(bytes.start - mapped_bytes.start)
+            .expect("mapped range is larger than memory");
+        let end = usize::try_from(bytes.end - mapped_bytes.start)
+            .expect("mapped range is larger than memory");
+
+        Ok(TypedMappedRead {
+            mapped,
+            bytes: start..end,
+            _elements: PhantomData,
+        })
+    }
+}
+
+impl<T> fmt::Debug for AsyncTypedBufferSlice<'_, T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("AsyncTypedBufferSlice")
+            .field("buffer", &self.buffer)
+            .field("elements", &self.elements)
+            .finish()
+    }
+}
+
+/// The elements of a mapped [`AsyncTypedBufferSlice`], given by
+/// [`AsyncTypedBufferSlice::map_read`]. Unmaps the buffer when dropped, as [`MappedRead`] does.
+pub struct TypedMappedRead<'a, T> {
+    mapped: MappedRead<'a>,
+    /// The bytes of the mapping holding the elements.
+    bytes: Range<usize>,
+    _elements: PhantomData<fn() -> T>,
+}
+
+impl<T: Pod> Deref for TypedMappedRead<'_, T> {
+    type Target = [T];
+
+    fn deref(&self) -> &Self::Target {
+        bytemuck::cast_slice(&self.mapped[self.bytes.clone()])
+    }
+}
+
+impl<T: Pod> AsRef<[T]> for TypedMappedRead<'_, T> {
+    fn as_ref(&self) -> &[T] {
+        self
+    }
+}
+
+impl<T: Pod + fmt::Debug> fmt::Debug for TypedMappedRead<'_, T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_list().entries(self.iter()).finish()
+    }
+}
@@ -1,198 +1,687 @@
+use std::cell::UnsafeCell;
 use std::future::Future;
-use std::ops::DerefMut;
+use std::mem::ManuallyDrop;
+use std::panic::Location;
 use std::pin::Pin;
-use std::sync::{Arc, Mutex};
-use std::task::{Context, Poll, Waker};
-use wgpu::Maintain;
+use std::sync::atomic::{AtomicU8, Ordering};
+use std::sync::Arc;
+use std::task::{Context, Poll};
+#[cfg(not(target_arch = "wasm32"))]
+use std::task::{Wake, Waker};
+#[cfg(not(target_arch = "wasm32"))]
+use std::thread::Thread;
 
 #[cfg(not(target_arch = "wasm32"))]
-use std::sync::{
-    atomic::{AtomicBool, AtomicUsize, Ordering},
-    Weak,
-};
+use std::time::{Duration, Instant};
 
-use crate::AsyncDevice;
+use atomic_waker::AtomicWaker;
 
-/// Polls the device while-ever a future says there is something to poll.
-///
-/// This objects corresponds to a thread that parks itself when no futures are
-/// waiting on it, and then calls `device.poll(Maintain::Wait)` repeatedly to block
-/// while-ever it has work that a future is waiting on.
-///
-/// The thread dies when this object is dropped, and when the GPU has finished processing
-/// all active futures.
+use crate::async_buffer::MappableBuffer;
+use crate::pending::{Fail, OperationKind, PendingOperations};
 #[cfg(not(target_arch = "wasm32"))]
-#[derive(Debug)]
-pub(crate) struct PollLoop {
-    /// The number of futures still waiting on resolution from the GPU.
-    /// When this is 0, the thread can park itself.
-    has_work: Arc<AtomicUsize>,
-    is_done: Arc<AtomicBool>,
-    handle: Option<std::thread::JoinHandle<()>>,
-}
+use crate::poll::{PollToken, DEADLINE_POLL_INTERVAL};
+use crate::slot_pool::SlotPool;
+use crate::{AsyncDevice, Dispatcher, Error, SharedWgpuFuture, Task};
+
+/// Called with the result of a detached future, in place of storing the result.
+type Continuation<T> = Box<dyn FnOnce(Result<T, Error>) + Send>;
 
+/// Set by the first to give a result. Only they may write the result.
+const RESOLVING: u8 = 1 << 0;
+/// Set once the result has been written, after which the result may be taken.
+const COMPLETE: u8 = 1 << 1;
+/// Set once the continuation has been written. Whichever of this and [`COMPLETE`] is set second
+/// runs the continuation.
+const DETACHED: u8 = 1 << 2;
+/// Set once the poll token has been written. Whoever clears this drops the token.
 #[cfg(not(target_arch = "wasm32"))]
-impl PollLoop {
-    pub(crate) fn new(device: Weak<wgpu::Device>) -> Self {
-        let has_work = Arc::new(AtomicUsize::new(0));
-        let is_done = Arc::new(AtomicBool::new(false));
-        let locally_has_work = Arc::clone(&has_work);
-        let locally_is_done = Arc::clone(&is_done);
+const HAS_TOKEN: u8 = 1 << 3;
+/// Set once the future has taken the result.
+const TAKEN: u8 = 1 << 4;
+/// Set once the future has been dropped or cancelled without being detached, after which the
+/// result is never taken.
+const CANCELLED: u8 = 1 << 5;
+
+/// The state that both the future and the callback hold.
+///
+/// Works like a oneshot channel: each cell is only accessed by whoever the flags say owns it, so
+/// no lock is needed.
+struct WgpuFutureSharedState<T> {
+    flags: AtomicU8,
+    result: UnsafeCell<Option<Result<T, Error>>>,
+    waker: AtomicWaker,
+    /// Given the result rather than it being stored, once the future has been detached.
+    continuation: UnsafeCell<Option<Continuation<T>>>,
+    /// Held while the operation is unresolved and something is waiting on it, keeping the device polled.
+    /// Dropped as soon as the result is given, so that an unclaimed result doesn't keep the device polled.
+    #[cfg(not(target_arch = "wasm32"))]
+    poll_token: UnsafeCell<Option<PollToken>>,
+}
+
+// SAFETY: the cells are only ever accessed by one thread at a time, as given by the flags
+unsafe impl<T: Send> Sync for WgpuFutureSharedState<T> {}
+
+impl<T> WgpuFutureSharedState<T> {
+    fn new() -> Self {
         Self {
-            has_work,
-            is_done,
-            handle: Some(std::thread::spawn(move || {
-                while !locally_is_done.load(Ordering::Acquire) {
-                    while locally_has_work.load(Ordering::Acquire) != 0 {
-                        match device.upgrade() {
-                            None => {
-                                // If all other references to the device are dropped, don't keep hold of the device here
-                                locally_is_done.store(true, Ordering::Release);
-                                return;
-                            }
-                            Some(device) => device.poll(Maintain::Wait),
-                        };
-                    }
-
-                    std::thread::park();
-                }
-                drop(device);
-            })),
-        }
-    }
-
-    /// If the loop wasn't polling, start it polling.
-    fn start_polling(&self) -> PollToken {
-        let prev = self.has_work.fetch_add(1, Ordering::AcqRel);
-        debug_assert!(
-            prev < usize::MAX,
-            "cannot have more than `usize::MAX` outstanding operations on the GPU"
-        );
-        self.handle
-            .as_ref()
-            .expect("handle set to None on drop")
-            .thread()
-            .unpark();
-        PollToken {
-            work_count: Arc::clone(&self.has_work),
+            flags: AtomicU8::new(0),
+            result: UnsafeCell::new(None),
+            waker: AtomicWaker::new(),
+            continuation: UnsafeCell::new(None),
+            #[cfg(not(target_arch = "wasm32"))]
+            poll_token: UnsafeCell::new(None),
+        }
+    }
+
+    /// Whether a result has been given, even if it has since been taken.
+    fn is_complete(&self) -> bool {
+        self.flags.load(Ordering::Acquire) & COMPLETE != 0
+    }
+
+    /// Whether a result has been given and not yet taken.
+    fn is_ready(&self) -> bool {
+        self.flags.load(Ordering::Acquire) & (COMPLETE | TAKEN) == COMPLETE
+    }
+
+    /// Whether the future has taken the result.
+    #[cfg(feature = "futures")]
+    fn is_taken(&self) -> bool {
+        self.flags.load(Ordering::Acquire) & TAKEN != 0
+    }
+
+    /// Gives a result to the future if it doesn't yet have one, waking the future.
+    ///
+    /// Returns whether the result will be seen, which it won't be if the future already has a
+    /// result or has been cancelled.
+    fn resolve(&self, result: Result<T, Error>, dispatcher: Option<&Arc<dyn Dispatcher>>) -> bool {
+        if self.flags.fetch_or(RESOLVING, Ordering::Acquire) & RESOLVING != 0 {
+            return false;
+        }
+
+        // SAFETY: only the resolver which set `RESOLVING` writes the result, and the result isn't
+        // read until `COMPLETE` is set
+        unsafe { *self.result.get() = Some(result) };
+        let flags = self.flags.fetch_or(COMPLETE, Ordering::AcqRel);
+
+        if flags & DETACHED != 0 {
+            // SAFETY: we set `COMPLETE` after `DETACHED` was set
+            unsafe { self.run_continuation() };
+        } else {
+            match (dispatcher, self.waker.take()) {
+                (Some(dispatcher), Some(waker)) => dispatcher.dispatch(Task::wake(waker)),
+                (None, Some(waker)) => waker.wake(),
+                (_, None) => {}
+            }
+        }
+
+        #[cfg(not(target_arch = "wasm32"))]
+        self.release_poll_token();
+
+        flags & CANCELLED == 0
+    }
+
+    /// Takes the result, if it has been given.
+    ///
+    /// # Safety
+    ///
+    /// Must only be called by the future, before it is detached.
+    unsafe fn take_result(&self) -> Option<Result<T, Error>> {
+        if !self.is_complete() {
+            return None;
+        }
+
+        // SAFETY: the result has been written and won't be written again, and the continuation
+        // isn't going to read it since the future hasn't been detached
+        let result = unsafe { (*self.result.get()).take() };
+        if result.is_some() {
+            self.flags.fetch_or(TAKEN, Ordering::Release);
+        }
+        result
+    }
+
+    /// Makes the result be given to the function given, rather than being stored.
+    ///
+    /// # Safety
+    ///
+    /// Must only be called once, by the future.
+    unsafe fn detach(&self, continuation: Continuation<T>) {
+        // SAFETY: the continuation is only read once `DETACHED` is set, which only we set
+        unsafe { *self.continuation.get() = Some(continuation) };
+        let flags = self.flags.fetch_or(DETACHED, Ordering::AcqRel);
+
+        if flags & COMPLETE != 0 {
+            // SAFETY: we set `DETACHED` after `COMPLETE` was set
+            unsafe { self.run_continuation() };
+        }
+    }
+
+    /// Gives the result to the continuation.
+    ///
+    /// # Safety
+    ///
+    /// Must only be called by whichever of the resolver and the future set the second of
+    /// `COMPLETE` and `DETACHED`.
+    unsafe fn run_continuation(&self) {
+        // SAFETY: both the result and the continuation have been written, and won't be accessed by
+        // anyone else
+        let (continuation, result) = unsafe {
+            (
+                (*self.continuation.get()).take(),
+                (*self.result.get()).take(),
+            )
+        };
+
+        if let (Some(continuation), Some(result)) = (continuation, result) {
+            continuation(result)
+        }
+    }
+
+    /// Holds a poll token until the operation completes.
+    ///
+    /// # Safety
+    ///
+    /// Must only be called by the future.
+    #[cfg(not(target_arch = "wasm32"))]
+    unsafe fn hold_poll_token(&self, start_polling: impl FnOnce() -> PollToken) {
+        if self.flags.load(Ordering::Acquire) & (HAS_TOKEN | RESOLVING) != 0 {
+            return;
+        }
+
+        // SAFETY: the token is only read by whoever clears `HAS_TOKEN`, which isn't set. Since
+        // `RESOLVING` isn't set, the resolver hasn't yet cleared `HAS_TOKEN` to read an earlier token
+        unsafe { *self.poll_token.get() = Some(start_polling()) };
+        let flags = self.flags.fetch_or(HAS_TOKEN, Ordering::AcqRel);
+
+        // The resolver may have finished before seeing the token
+        if flags & COMPLETE != 0 {
+            self.release_poll_token();
+        }
+    }
+
+    /// Drops the poll token, if one is held.
+    #[cfg(not(target_arch = "wasm32"))]
+    fn release_poll_token(&self) {
+        if self.flags.fetch_and(!HAS_TOKEN, Ordering::AcqRel) & HAS_TOKEN != 0 {
+            // SAFETY: `HAS_TOKEN` was set, so the token has been written, and we cleared it so no one
+            // else reads it
+            drop(unsafe { (*self.poll_token.get()).take() });
         }
     }
 }
 
-#[cfg(not(target_arch = "wasm32"))]
-impl Drop for PollLoop {
-    fn drop(&mut self) {
-        self.is_done.store(true, Ordering::Release);
+/// Returns a state to the device's pool of slots if nothing else holds it, so that it can be reused
+/// by a later operation.
+type Recycle<T> = fn(&SlotPool, Arc<WgpuFutureSharedState<T>>);
 
-        let handle = self.handle.take().expect("PollLoop dropped twice");
-        handle.thread().unpark();
-        handle.join().expect("PollLoop thread panicked");
+fn recycle<T: Send + 'static>(slots: &SlotPool, mut state: Arc<WgpuFutureSharedState<T>>) {
+    // Unique once both the future and callback are done with the state, and it is no longer pending
+    if let Some(unique) = Arc::get_mut(&mut state) {
+        *unique = WgpuFutureSharedState::new();
+        slots.put(state);
     }
 }
 
-/// RAII indicating that polling is occurring, while this token is held.
-#[cfg(not(target_arch = "wasm32"))]
-struct PollToken {
-    work_count: Arc<AtomicUsize>,
+impl<T: Send> Fail for WgpuFutureSharedState<T> {
+    fn fail(&self, error: Error, dispatcher: Option<&Arc<dyn Dispatcher>>) {
+        self.resolve(Err(error), dispatcher);
+    }
 }
 
-#[cfg(not(target_arch = "wasm32"))]
-impl Drop for PollToken {
+/// The half of a [`WgpuFuture`] that is moved into the callback given to `wgpu`.
+///
+/// If this is dropped without [`WgpuCallback::complete`] being called then the future resolves with
+/// [`Error::CallbackDropped`], since the operation will never complete.
+pub(crate) struct WgpuCallback<T> {
+    /// Only taken on drop, to be recycled.
+    state: ManuallyDrop<Arc<WgpuFutureSharedState<T>>>,
+    pending: Arc<PendingOperations>,
+    slots: Arc<SlotPool>,
+    recycle: Recycle<T>,
+    /// The id of the operation within the device's pending operations. `None` once the future
+    /// has been resolved.
+    id: Option<u64>,
+}
+
+impl<T> WgpuCallback<T> {
+    /// Resolves the future with the result of the operation, returning whether the result will be
+    /// seen. It won't be if the future has already failed, or has been cancelled.
+    pub(crate) fn complete(mut self, result: Result<T, Error>) -> bool {
+        self.resolve(result)
+    }
+
+    fn resolve(&mut self, result: Result<T, Error>) -> bool {
+        let Some(id) = self.id.take() else {
+            return false;
+        };
+
+        // Stop tracking the operation first, since resolving may run a continuation which drops
+        // the last device, which would then report the operation as still pending
+        self.pending.remove(id);
+        self.state.resolve(result, self.pending.dispatcher())
+    }
+}
+
+impl<T> Drop for WgpuCallback<T> {
     fn drop(&mut self) {
-        // On the web we don't poll, so don't do anything
-        #[cfg(not(target_arch = "wasm32"))]
-        {
-            let prev = self.work_count.fetch_sub(1, Ordering::AcqRel);
-            debug_assert!(
-                prev > 0,
-                "stop_polling was called without calling start_polling"
-            );
-        }
+        self.resolve(Err(Error::CallbackDropped));
+
+        // SAFETY: the state isn't used again
+        let state = unsafe { ManuallyDrop::take(&mut self.state) };
+        (self.recycle)(&self.slots, state);
     }
 }
 
-/// The state that both the future and the callback hold.
-struct WgpuFutureSharedState<T> {
-    result: Option<T>,
-    waker: Option<Waker>,
+/// The callback given by [`AsyncDevice::do_async_unboxed`], which resolves its future once called.
+///
+/// If this is dropped without [`Callback::complete`] being called then the future resolves with
+/// [`Error::CallbackDropped`], since the operation will never complete.
+pub struct Callback<R>(WgpuCallback<R>);
+
+impl<R> Callback<R> {
+    pub(crate) fn new(callback: WgpuCallback<R>) -> Self {
+        Self(callback)
+    }
+
+    /// Resolves the future with the result of the operation.
+    pub fn complete(self, result: R) {
+        self.0.complete(Ok(result));
+    }
+}
+
+impl<R> std::fmt::Debug for Callback<R> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Callback").finish_non_exhaustive()
+    }
 }
 
 /// A future that can be awaited for once a callback completes. Created using [`AsyncDevice::do_async`].
+///
+/// Resolves with an error if the operation can never complete, for example if the device is lost.
 pub struct WgpuFuture<T> {
     device: AsyncDevice,
-    state: Arc<Mutex<WgpuFutureSharedState<T>>>,
-
+    /// Only taken on drop, to be recycled.
+    state: ManuallyDrop<Arc<WgpuFutureSharedState<T>>>,
+    recycle: Recycle<T>,
+    /// The buffer being mapped, if this is a mapping, to be unmapped if the future is cancelled.
+    mapping: Option<Arc<MappableBuffer>>,
+    /// Set by [`WgpuFuture::cancel`], until the future has given [`Error::Cancelled`].
+    cancelled: bool,
+    /// The id of the operation within the device's pending operations, if it is being tracked.
     #[cfg(not(target_arch = "wasm32"))]
-    poll_token: Option<PollToken>,
+    id: Option<u64>,
+    #[cfg(not(target_arch = "wasm32"))]
+    deadline: Option<Instant>,
 }
 
 impl<T: Send + 'static> WgpuFuture<T> {
-    pub(crate) fn new(device: AsyncDevice) -> Self {
-        Self {
-            device,
-            state: Arc::new(Mutex::new(WgpuFutureSharedState {
-                result: None,
-                waker: None,
-            })),
+    /// Creates a future, along with the callback that resolves it.
+    ///
+    /// If the device has been lost or shut down then the future is created already failed, and
+    /// there is no callback.
+    #[track_caller]
+    pub(crate) fn new(device: AsyncDevice, kind: OperationKind) -> (Self, Option<WgpuCallback<T>>) {
+        let state = device
+            .shared
+            .slots
+            .take()
+            .unwrap_or_else(|| Arc::new(WgpuFutureSharedState::new()));
 
+        let weak_state = Arc::downgrade(&state);
+        let id = match device
+            .shared
+            .pending
+            .insert(weak_state, kind, Location::caller())
+        {
+            Ok(id) => Some(id),
+            Err(error) => {
+                state.resolve(Err(error), None);
+                None
+            }
+        };
+
+        let callback = id.map(|id| WgpuCallback {
+            state: ManuallyDrop::new(Arc::clone(&state)),
+            pending: Arc::clone(&device.shared.pending),
+            slots: Arc::clone(&device.shared.slots),
+            recycle: recycle::<T>,
+            id: Some(id),
+        });
+        let future = Self {
+            device,
+            state: ManuallyDrop::new(state),
+            recycle: recycle::<T>,
+            mapping: None,
+            cancelled: false,
             #[cfg(not(target_arch = "wasm32"))]
-            poll_token: None,
-        }
+            id,
+            #[cfg(not(target_arch = "wasm32"))]
+            deadline: None,
+        };
+
+        (future, callback)
+    }
+}
+
+impl<T> WgpuFuture<T> {
+    /// Starts polling the device for this future now, rather than when it is first awaited, so that
+    /// the operation progresses even if the future is awaited much later. The device stops being
+    /// polled for this future once the operation completes.
+    ///
+    /// See also [`AsyncDeviceBuilder::eager`](crate::AsyncDeviceBuilder::eager). On the web the
+    /// device is never polled, so this does nothing.
+    pub fn eager(self) -> Self {
+        #[cfg(not(target_arch = "wasm32"))]
+        self.ensure_polling();
+        self
+    }
+
+    /// Converts this future into one which can be cloned, such that many tasks can await the same
+    /// operation. Every clone resolves with a clone of the result.
+    pub fn shared(self) -> SharedWgpuFuture<T>
+    where
+        T: Clone,
+    {
+        SharedWgpuFuture::new(self)
     }
 
-    /// Generates a callback function for this future that wakes the waker and sets the shared state.
-    pub(crate) fn callback(&self) -> Box<dyn FnOnce(T) + Send> {
-        let shared_state = Arc::clone(&self.state);
-        return Box::new(move |res: T| {
-            let mut lock = shared_state
-                .lock()
-                .expect("wgpu future was poisoned on complete");
-            let shared_state = lock.deref_mut();
-            shared_state.result = Some(res);
+    /// Lets the operation run to completion without anything waiting on it, discarding the result.
+    ///
+    /// Unlike dropping the future, the device keeps being polled until the operation completes.
+    pub fn detach(self)
+    where
+        T: Send + 'static,
+    {
+        self.detach_with(drop)
+    }
 
-            if let Some(waker) = shared_state.waker.take() {
-                waker.wake()
+    /// Lets the operation run to completion without anything waiting on it, then calls the function
+    /// given with the result. The device keeps being polled until the operation completes.
+    ///
+    /// The function is called on whichever thread completes the operation, which is usually the
+    /// thread polling the device, so should be quick. If the device has a [`Dispatcher`], the
+    /// function is given to that to run instead.
+    pub(crate) fn detach_with(mut self, then: impl FnOnce(Result<T, Error>) + Send + 'static)
+    where
+        T: Send + 'static,
+    {
+        // A cancelled future has nothing more to give than the cancellation
+        if self.state.flags.load(Ordering::Acquire) & CANCELLED != 0 {
+            if let Some(res) = self.take() {
+                then(res);
             }
-        });
+            return;
+        }
+
+        // The continuation is given the mapping instead
+        self.mapping = None;
+
+        // Keep the device polled for the operation, even though nothing is waiting on it
+        #[cfg(not(target_arch = "wasm32"))]
+        self.ensure_polling();
+
+        let continuation: Continuation<T> = match self.device.shared.pending.dispatcher() {
+            Some(dispatcher) => {
+                let dispatcher = Arc::clone(dispatcher);
+                Box::new(move |res| dispatcher.dispatch(Task::new(move || then(res))))
+            }
+            None => Box::new(then),
+        };
+
+        // SAFETY: we are the future, and consuming it means this is only called once
+        unsafe { self.state.detach(continuation) };
+    }
+
+    /// Stops waiting on the operation, returning whether it was abandoned before it completed.
+    ///
+    /// Work already given to the GPU can't be stopped, so the operation still runs to completion,
+    /// but its result is dropped. If this future is a [mapping](crate::AsyncBufferSlice::map_async),
+    /// the buffer is unmapped once the mapping completes, or immediately if it already has. Dropping
+    /// the future does the same.
+    ///
+    /// Unless its result had already been taken, the future then resolves with
+    /// [`Error::Cancelled`].
+    pub fn cancel(&mut self) -> bool {
+        if self.state.flags.load(Ordering::Acquire) & (CANCELLED | TAKEN) == 0 {
+            self.cancelled = true;
+        }
+        self.abandon()
+    }
+
+    /// Unmaps the buffer given if this future is cancelled after the mapping completes.
+    pub(crate) fn unmap_on_cancel(mut self, buffer: Arc<MappableBuffer>) -> Self {
+        self.mapping = Some(buffer);
+        self
+    }
+
+    /// Stops waiting on the operation, returning whether it was abandoned before it completed.
+    fn abandon(&mut self) -> bool {
+        // Detached futures are still waited on, by their continuation
+        if self.state.flags.load(Ordering::Acquire) & DETACHED != 0 {
+            return false;
+        }
+
+        #[cfg(not(target_arch = "wasm32"))]
+        self.state.release_poll_token();
+
+        let flags = self.state.flags.fetch_or(CANCELLED, Ordering::AcqRel);
+        if flags & (CANCELLED | TAKEN) != 0 {
+            return false;
+        }
+        if flags & COMPLETE == 0 {
+            // Whatever completes the operation will see that it was cancelled
+            return true;
+        }
+
+        // The operation completed, but its result was never taken
+        // SAFETY: we are the future, and haven't been detached
+        let result = unsafe { self.state.take_result() };
+        if let (Some(Ok(_)), Some(buffer)) = (result, self.mapping.take()) {
+            buffer.unmap();
+        }
+        false
+    }
+
+    /// Whether the operation has completed, so that awaiting this future won't wait.
+    ///
+    /// Unlike polling the future, this doesn't poll the device, so only notices completions once
+    /// the device has been polled elsewhere, for example because this future is
+    /// [eager](WgpuFuture::eager).
+    pub fn is_ready(&self) -> bool {
+        #[cfg(not(target_arch = "wasm32"))]
+        self.check_deadline();
+
+        if self.state.flags.load(Ordering::Acquire) & CANCELLED != 0 {
+            return self.cancelled;
+        }
+        self.state.is_ready()
+    }
+
+    /// Takes the result of the operation if it has completed, without waiting.
+    ///
+    /// As with [`WgpuFuture::is_ready`], this doesn't poll the device. Once the result has been
+    /// taken, this gives `None`, and the future must not be polled again.
+    pub fn try_take(&mut self) -> Option<Result<T, Error>> {
+        #[cfg(not(target_arch = "wasm32"))]
+        self.check_deadline();
+
+        self.take()
+    }
+
+    /// Takes the result if it has been given, or gives [`Error::Cancelled`] once if the future was
+    /// cancelled.
+    fn take(&mut self) -> Option<Result<T, Error>> {
+        if self.state.flags.load(Ordering::Acquire) & CANCELLED != 0 {
+            return std::mem::take(&mut self.cancelled).then_some(Err(Error::Cancelled));
+        }
+
+        // SAFETY: we are the future, and haven't been detached since that consumes the future
+        unsafe { self.state.take_result() }
+    }
+
+    /// If the operation is unresolved, makes sure the device is being polled for it.
+    #[cfg(not(target_arch = "wasm32"))]
+    fn ensure_polling(&self) {
+        // SAFETY: we are the future
+        unsafe {
+            self.state
+                .hold_poll_token(|| self.device.shared.poller.start_polling())
+        };
     }
 }
 
-impl<T> Future for WgpuFuture<T> {
-    type Output = T;
+#[cfg(not(target_arch = "wasm32"))]
+impl<T> WgpuFuture<T> {
+    /// Makes this future resolve with [`Error::Elapsed`] if the operation has not completed
+    /// by the given deadline.
+    ///
+    /// The poll loop only notices deadlines between calls to `device.poll`, so deadlines should be
+    /// set before the future is first awaited.
+    pub fn with_deadline(mut self, deadline: Instant) -> Self {
+        if let Some(id) = self.id {
+            self.device.shared.pending.set_deadline(id, deadline);
+        }
+        self.deadline = Some(deadline);
+        self
+    }
+
+    /// Makes this future resolve with [`Error::Elapsed`] if the operation has not completed
+    /// within the given duration from now.
+    ///
+    /// See [`WgpuFuture::with_deadline`].
+    pub fn with_timeout(self, timeout: Duration) -> Self {
+        self.with_deadline(Instant::now() + timeout)
+    }
+
+    /// Blocks the current thread until the operation completes, without needing an executor.
+    ///
+    /// The device is polled from the current thread, waiting for submitted work to finish, so this
+    /// works even if the device's [`PollDriver`](crate::PollDriver) isn't running. This must not be
+    /// called from within a callback given to `wgpu`, where the device can't be polled. On the web
+    /// blocking is impossible, so this isn't available.
+    ///
+    /// If the future has a [deadline](WgpuFuture::with_deadline), this returns once it passes, even
+    /// if the GPU is still busy with the work.
+    pub fn wait(mut self) -> Result<T, Error> {
+        let waker = Waker::from(Arc::new(Unpark(std::thread::current())));
+
+        // Keep the device polled by its poller too, for as long as the operation is unresolved
+        self.ensure_polling();
+
+        // Waiting for the GPU could take past the deadline, so with one the device is instead polled
+        // without blocking, as the poll loop does
+        let maintain = match self.deadline {
+            Some(_) => wgpu::Maintain::Poll,
+            None => wgpu::Maintain::Wait,
+        };
+
+        loop {
+            self.device.shared.poller.poll_now(
+                &self.device,
+                maintain.clone(),
+                &self.device.shared.pending,
+            );
+            if let Some(res) = self.try_take() {
+                return res;
+            }
 
-    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
-        // Poll whenever we enter to see if we can avoid waiting altogether
-        self.device.poll(Maintain::Poll);
+            // Not everything completes by polling, so wait to be woken by whatever does complete it.
+            // Some operations are only started by a callback run while polling, and so complete on a
+            // later poll, so poll again soon even if nothing wakes us
+            self.state.waker.register(&waker);
+            if let Some(res) = self.try_take() {
+                return res;
+            }
+            let timeout = self.deadline.map_or(DEADLINE_POLL_INTERVAL, |deadline| {
+                deadline
+                    .saturating_duration_since(Instant::now())
+                    .min(DEADLINE_POLL_INTERVAL)
+            });
+            std::thread::park_timeout(timeout);
+        }
+    }
 
-        // Check with scoped lock
+    /// Fails the operation if its deadline has passed, since the poll loop may not have been
+    /// running to notice.
+    fn check_deadline(&self) {
+        if self
+            .deadline
+            .is_some_and(|deadline| deadline <= Instant::now())
         {
-            let Self {
-                state,
-                #[cfg(not(target_arch = "wasm32"))]
-                poll_token,
-                ..
-            } = self.as_mut().get_mut();
-            let mut lock = state.lock().expect("wgpu future was poisoned on poll");
-
-            if let Some(res) = lock.result.take() {
-                #[cfg(not(target_arch = "wasm32"))]
-                {
-                    // Drop token, stopping poll loop.
-                    *poll_token = None;
-                }
-
-                return Poll::Ready(res);
+            self.state.resolve(Err(Error::Elapsed), None);
+            if let Some(id) = self.id {
+                self.device.shared.pending.remove(id);
             }
+        }
+    }
+}
+
+/// Wakes a thread blocked in [`WgpuFuture::wait`].
+#[cfg(not(target_arch = "wasm32"))]
+struct Unpark(Thread);
 
-            lock.waker = Some(cx.waker().clone());
+#[cfg(not(target_arch = "wasm32"))]
+impl Wake for Unpark {
+    fn wake(self: Arc<Self>) {
+        self.0.unpark()
+    }
+}
+
+impl<T> Drop for WgpuFuture<T> {
+    fn drop(&mut self) {
+        self.abandon();
+
+        // SAFETY: the state isn't used again
+        let state = unsafe { ManuallyDrop::take(&mut self.state) };
+        (self.recycle)(&self.device.shared.slots, state);
+    }
+}
+
+impl<T> Future for WgpuFuture<T> {
+    type Output = Result<T, Error>;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let this = self.get_mut();
+        if let Some(res) = this.take() {
+            return Poll::Ready(res);
         }
 
-        // If we're not ready, make sure the poll loop is running (on non-WASM)
+        // Otherwise poll the device to see if we can avoid waiting altogether
         #[cfg(not(target_arch = "wasm32"))]
-        if self.poll_token.is_none() {
-            self.poll_token = Some(self.device.poll_loop.start_polling());
+        this.device.shared.poller.poll_now(
+            &this.device,
+            wgpu::Maintain::Poll,
+            &this.device.shared.pending,
+        );
+        #[cfg(target_arch = "wasm32")]
+        this.device.poll(wgpu::Maintain::Poll);
+
+        #[cfg(not(target_arch = "wasm32"))]
+        this.check_deadline();
+
+        if let Some(res) = this.take() {
+            return Poll::Ready(res);
+        }
+
+        // Check again after registering, in case the result was given in between
+        this.state.waker.register(cx.waker());
+        if let Some(res) = this.take() {
+            return Poll::Ready(res);
         }
 
+        // If we're not ready, make sure the poll loop is running (on non-WASM)
+        #[cfg(not(target_arch = "wasm32"))]
+        this.ensure_polling();
+
         Poll::Pending
     }
 }
+
+#[cfg(feature = "futures")]
+impl<T> futures_core::FusedFuture for WgpuFuture<T> {
+    fn is_terminated(&self) -> bool {
+        if self.state.flags.load(Ordering::Acquire) & CANCELLED != 0 {
+            return !self.cancelled;
+        }
+        self.state.is_taken()
+    }
+}
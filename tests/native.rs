@@ -1,39 +1,57 @@
 #![cfg(not(target_arch = "wasm32"))]
 
 use std::{
+    future::Future,
     ops::Deref,
-    sync::{atomic::AtomicBool, Arc},
+    sync::{
+        atomic::{AtomicBool, AtomicUsize},
+        Arc, Mutex,
+    },
     time::Duration,
 };
 
-use wgpu_async::{AsyncDevice, AsyncQueue};
+use wgpu_async::{
+    AsyncDevice, AsyncDeviceBuilder, AsyncQueue, Error, MipSource, OperationKind, PollHealth, Task,
+};
 
-fn setup() -> (AsyncDevice, AsyncQueue) {
-    pollster::block_on(async {
-        let instance = wgpu::Instance::new(wgpu::InstanceDescriptor::default());
-        let adapter = instance
-            .request_adapter(&wgpu::RequestAdapterOptions {
-                power_preference: wgpu::PowerPreference::HighPerformance,
-                compatible_surface: None,
-                force_fallback_adapter: true,
-            })
-            .await
-            .expect("missing adapter");
-        let (device, queue) = adapter
-            .request_device(
-                &wgpu::DeviceDescriptor {
-                    required_features: wgpu::Features::empty(),
-                    required_limits: adapter.limits(),
-                    label: None,
-                    memory_hints: wgpu::MemoryHints::default(),
-                },
-                None,
-            )
-            .await
-            .expect("missing device");
+fn request_device() -> (Arc<wgpu::Device>, Arc<wgpu::Queue>) {
+    request_device_with(wgpu::Features::empty()).expect("missing features")
+}
 
-        wgpu_async::wrap(Arc::new(device), Arc::new(queue))
-    })
+fn request_adapter() -> wgpu::Adapter {
+    let instance = wgpu::Instance::new(wgpu::InstanceDescriptor::default());
+    pollster::block_on(instance.request_adapter(&wgpu::RequestAdapterOptions {
+        power_preference: wgpu::PowerPreference::HighPerformance,
+        compatible_surface: None,
+        force_fallback_adapter: true,
+    }))
+    .expect("missing adapter")
+}
+
+/// Requests a device with the features given, if the adapter has them.
+fn request_device_with(features: wgpu::Features) -> Option<(Arc<wgpu::Device>, Arc<wgpu::Queue>)> {
+    let adapter = request_adapter();
+    if !adapter.features().contains(features) {
+        return None;
+    }
+
+    let (device, queue) = pollster::block_on(adapter.request_device(
+        &wgpu::DeviceDescriptor {
+            required_features: features,
+            required_limits: adapter.limits(),
+            label: None,
+            memory_hints: wgpu::MemoryHints::default(),
+        },
+        None,
+    ))
+    .expect("missing device");
+
+    Some((Arc::new(device), Arc::new(queue)))
+}
+
+fn setup() -> (AsyncDevice, AsyncQueue) {
+    let (device, queue) = request_device();
+    wgpu_async::wrap(device, queue)
 }
 
 #[test]
@@ -109,8 +127,8 @@ fn after_submit_empty_commands_and_map_buffer_twice_loop_stops() {
     let f2 = async_buffer2.slice(..).map_async(wgpu::MapMode::Write);
 
     pollster::block_on(async {
-        q1.await;
-        q2.await;
+        q1.await.unwrap();
+        q2.await.unwrap();
         f1.await.unwrap();
         f2.await.unwrap();
     });
@@ -136,3 +154,1629 @@ fn after_submit_empty_commands_and_map_buffer_twice_loop_stops() {
     device.poll(wgpu::Maintain::Wait);
     assert!(is_mapped.load(std::sync::atomic::Ordering::Acquire));
 }
+
+#[test]
+fn device_lost_resolves_pending_futures() {
+    let (device, _) = setup();
+
+    // Keep hold of the callback without ever calling it, so only losing the device can resolve the future
+    let callbacks = Arc::new(Mutex::new(Vec::new()));
+    let local_callbacks = Arc::clone(&callbacks);
+    let future = device.do_async(move |callback: Box<dyn FnOnce(()) + Send>| {
+        local_callbacks.lock().unwrap().push(callback)
+    });
+
+    device.destroy();
+    device.poll(wgpu::Maintain::Wait);
+
+    let res = pollster::block_on(future);
+    assert!(
+        matches!(
+            res,
+            Err(Error::DeviceLost {
+                reason: wgpu::DeviceLostReason::Destroyed,
+                ..
+            })
+        ),
+        "{res:?}"
+    );
+
+    // Futures created after the device is lost resolve immediately
+    let res =
+        pollster::block_on(device.do_async(|callback: Box<dyn FnOnce(()) + Send>| {
+            callbacks.lock().unwrap().push(callback)
+        }));
+    assert!(matches!(res, Err(Error::DeviceLost { .. })), "{res:?}");
+}
+
+#[test]
+fn device_lost_is_forwarded() {
+    let (device, queue) = request_device();
+
+    let (sender, receiver) = std::sync::mpsc::channel();
+    let sender = Mutex::new(sender);
+    let (device, _) = AsyncDeviceBuilder::new()
+        .on_device_lost(move |reason, _| sender.lock().unwrap().send(reason).unwrap())
+        .wrap(device, queue)
+        .unwrap();
+
+    let callbacks = Arc::new(Mutex::new(Vec::new()));
+    let local_callbacks = Arc::clone(&callbacks);
+    let future = device.do_async(move |callback: Box<dyn FnOnce(()) + Send>| {
+        local_callbacks.lock().unwrap().push(callback)
+    });
+
+    device.destroy();
+    device.poll(wgpu::Maintain::Wait);
+
+    // Operations still fail, as well as the callback being called
+    assert!(matches!(
+        pollster::block_on(future),
+        Err(Error::DeviceLost { .. })
+    ));
+    assert_eq!(
+        receiver.recv_timeout(Duration::from_secs(5)),
+        Ok(wgpu::DeviceLostReason::Destroyed)
+    );
+}
+
+#[test]
+fn dropped_callback_resolves_future() {
+    let (device, _) = setup();
+
+    let future = device.do_async(|callback: Box<dyn FnOnce(()) + Send>| drop(callback));
+
+    assert_eq!(pollster::block_on(future), Err(Error::CallbackDropped));
+}
+
+#[test]
+fn timeout_resolves_stuck_future() {
+    let (device, _) = setup();
+
+    let callbacks = Arc::new(Mutex::new(Vec::new()));
+    let local_callbacks = Arc::clone(&callbacks);
+    let future = device
+        .do_async(move |callback: Box<dyn FnOnce(()) + Send>| {
+            local_callbacks.lock().unwrap().push(callback)
+        })
+        .with_timeout(Duration::from_millis(100));
+
+    let start = std::time::Instant::now();
+    assert_eq!(pollster::block_on(future), Err(Error::Elapsed));
+    assert!(start.elapsed() < Duration::from_secs(5));
+
+    // The callback firing late does nothing
+    let callback = callbacks.lock().unwrap().pop().unwrap();
+    callback(());
+}
+
+#[test]
+fn timeout_after_completion_is_ignored() {
+    let (device, queue) = setup();
+
+    let commands = device
+        .create_command_encoder(&wgpu::CommandEncoderDescriptor { label: None })
+        .finish();
+    let future = queue
+        .submit(vec![commands])
+        .with_deadline(std::time::Instant::now() + Duration::from_secs(60));
+
+    assert_eq!(pollster::block_on(future), Ok(()));
+}
+
+#[test]
+fn driver_polls_until_devices_dropped() {
+    let (device, queue) = request_device();
+    let (device, queue, driver) = wgpu_async::wrap_with_driver(device, queue, sleep).unwrap();
+
+    let driver_thread = std::thread::spawn(move || pollster::block_on(driver));
+
+    let async_buffer = device.create_buffer(&wgpu::BufferDescriptor {
+        label: None,
+        size: 8192,
+        usage: wgpu::BufferUsages::MAP_READ,
+        mapped_at_creation: false,
+    });
+    let commands = device
+        .create_command_encoder(&wgpu::CommandEncoderDescriptor { label: None })
+        .finish();
+
+    pollster::block_on(async {
+        queue.submit(vec![commands]).await.unwrap();
+        async_buffer
+            .slice(..)
+            .map_async(wgpu::MapMode::Read)
+            .await
+            .unwrap();
+    });
+    async_buffer.unmap();
+
+    drop((device, queue, async_buffer));
+    driver_thread.join().unwrap();
+}
+
+#[test]
+fn driver_is_not_given_for_wrapped_devices() {
+    let (device, queue) = request_device();
+    let _wrapped = wgpu_async::wrap(Arc::clone(&device), Arc::clone(&queue));
+
+    assert!(wgpu_async::wrap_with_driver(device, queue, sleep).is_none());
+}
+
+/// Sleeps for the duration given, in place of an executor's timer.
+fn sleep(duration: Duration) -> impl Future<Output = ()> + Send {
+    let until = std::time::Instant::now() + duration;
+    let mut started = false;
+    std::future::poll_fn(move |cx| {
+        if std::time::Instant::now() >= until {
+            return std::task::Poll::Ready(());
+        }
+        if !std::mem::replace(&mut started, true) {
+            let waker = cx.waker().clone();
+            std::thread::spawn(move || {
+                std::thread::sleep(until.saturating_duration_since(std::time::Instant::now()));
+                waker.wake()
+            });
+        }
+        std::task::Poll::Pending
+    })
+}
+
+#[test]
+fn driver_waits_between_polls() {
+    let (device, queue) = request_device();
+    let (device, queue, mut driver) = wgpu_async::wrap_with_driver(device, queue, sleep).unwrap();
+
+    let polls = Arc::new(AtomicUsize::new(0));
+    let driver_polls = Arc::clone(&polls);
+    let driver_thread = std::thread::spawn(move || {
+        pollster::block_on(std::future::poll_fn(|cx| {
+            driver_polls.fetch_add(1, std::sync::atomic::Ordering::Relaxed);
+            std::pin::Pin::new(&mut driver).poll(cx)
+        }))
+    });
+
+    // Never completes, so the driver polls for the whole timeout
+    let mut callbacks = Vec::new();
+    let future = device
+        .do_async(|callback: Box<dyn FnOnce(()) + Send>| callbacks.push(callback))
+        .with_timeout(Duration::from_millis(200));
+    assert_eq!(pollster::block_on(future), Err(Error::Elapsed));
+
+    // Polling at most every millisecond, rather than as fast as the executor allows
+    let polls = polls.load(std::sync::atomic::Ordering::Relaxed);
+    assert!(polls < 1000, "driver was polled {polls} times");
+
+    drop((device, queue, callbacks));
+    driver_thread.join().unwrap();
+}
+
+#[test]
+fn wrapping_twice_shares_existing_device() {
+    let (device, queue) = request_device();
+    assert!(AsyncDevice::from_existing(&device).is_none());
+
+    let (device1, queue1) = wgpu_async::wrap(Arc::clone(&device), Arc::clone(&queue));
+    let (device2, _) = wgpu_async::wrap(Arc::clone(&device), Arc::clone(&queue));
+    let device3 = AsyncDevice::from_existing(&device).expect("device was wrapped");
+
+    // An operation started through one wrapper is tracked by the others, since they share state
+    let mut callbacks = Vec::new();
+    let line = line!() + 1;
+    let pending = device1.do_async(|callback: Box<dyn FnOnce(()) + Send>| callbacks.push(callback));
+    for wrapper in [&device2, &device3] {
+        let operations = wrapper.pending_operations();
+        assert_eq!(operations.len(), 1);
+        assert_eq!(operations[0].location.line(), line);
+    }
+    callbacks.pop().unwrap()(());
+    assert_eq!(pollster::block_on(pending), Ok(()));
+    assert!(device2.pending_operations().is_empty());
+
+    let async_buffer = device3.create_buffer(&wgpu::BufferDescriptor {
+        label: None,
+        size: 8192,
+        usage: wgpu::BufferUsages::MAP_READ,
+        mapped_at_creation: false,
+    });
+    let commands = device2
+        .create_command_encoder(&wgpu::CommandEncoderDescriptor { label: None })
+        .finish();
+
+    pollster::block_on(async {
+        queue1.submit(vec![commands]).await.unwrap();
+        async_buffer
+            .slice(..)
+            .map_async(wgpu::MapMode::Read)
+            .await
+            .unwrap();
+    });
+    async_buffer.unmap();
+
+    drop((device1, queue1, device2, device3, async_buffer));
+    assert!(AsyncDevice::from_existing(&device).is_none());
+}
+
+#[test]
+fn shutdown_waits_for_outstanding_work() {
+    let (raw_device, raw_queue) = request_device();
+    let (device, queue) = wgpu_async::wrap(Arc::clone(&raw_device), Arc::clone(&raw_queue));
+
+    let commands = device
+        .create_command_encoder(&wgpu::CommandEncoderDescriptor { label: None })
+        .finish();
+    let submitted = queue.submit(vec![commands]);
+
+    let report = pollster::block_on(device.shutdown());
+    assert_eq!(report.timed_out, 0);
+    assert!(!report.poll_thread_panicked);
+
+    // The work was completed before the shutdown finished
+    assert_eq!(pollster::block_on(submitted), Ok(()));
+
+    // No more work is accepted
+    let future =
+        device.do_async(|_: Box<dyn FnOnce(()) + Send>| panic!("operation started after shutdown"));
+    assert_eq!(pollster::block_on(future), Err(Error::ShutDown));
+
+    // Wrapping the device again starts afresh
+    assert!(AsyncDevice::from_existing(&raw_device).is_none());
+    let (_device, queue) = wgpu_async::wrap(raw_device, raw_queue);
+    assert_eq!(pollster::block_on(queue.submit([])), Ok(()));
+}
+
+#[test]
+fn shutdown_timeout_fails_stuck_work() {
+    let (device, _) = setup();
+
+    let callbacks = Arc::new(Mutex::new(Vec::new()));
+    let local_callbacks = Arc::clone(&callbacks);
+    let future = device.do_async(move |callback: Box<dyn FnOnce(()) + Send>| {
+        local_callbacks.lock().unwrap().push(callback)
+    });
+
+    let report = pollster::block_on(device.shutdown_timeout(Duration::from_millis(100)));
+    assert_eq!(report.pending.len(), 1);
+    assert_eq!(report.pending[0].kind, OperationKind::Custom);
+    assert_eq!(report.pending[0].location.file(), file!());
+    assert_eq!(report.timed_out, 1);
+
+    assert_eq!(pollster::block_on(future), Err(Error::Elapsed));
+}
+
+#[test]
+fn poll_panic_resolves_pending_futures() {
+    let (device, _) = setup();
+    assert_eq!(device.poll_health(), PollHealth::Healthy);
+
+    let async_buffer = device.create_buffer(&wgpu::BufferDescriptor {
+        label: None,
+        size: 8192,
+        usage: wgpu::BufferUsages::MAP_READ,
+        mapped_at_creation: false,
+    });
+
+    // Map without the async api, with a callback which panics on the poll thread
+    let buffer: &wgpu::Buffer = async_buffer.deref();
+    buffer
+        .slice(..)
+        .map_async(wgpu::MapMode::Read, |_| panic!("panic in callback"));
+
+    let callbacks = Arc::new(Mutex::new(Vec::new()));
+    let local_callbacks = Arc::clone(&callbacks);
+    let future = device.do_async(move |callback: Box<dyn FnOnce(()) + Send>| {
+        local_callbacks.lock().unwrap().push(callback)
+    });
+
+    assert_eq!(
+        pollster::block_on(future),
+        Err(Error::PollPanicked("panic in callback".to_owned()))
+    );
+    assert_eq!(
+        device.poll_health(),
+        PollHealth::Panicked("panic in callback".to_owned())
+    );
+}
+
+#[test]
+fn builder_configures_poll_thread() {
+    let (device, queue) = request_device();
+
+    let (sender, receiver) = std::sync::mpsc::channel();
+    let (device, queue) = AsyncDeviceBuilder::new()
+        .thread_name("test-poll-thread")
+        .stack_size(512 * 1024)
+        .on_thread_start(move || {
+            sender
+                .send(std::thread::current().name().map(str::to_owned))
+                .unwrap()
+        })
+        .wrap(device, queue)
+        .unwrap();
+
+    let name = receiver.recv_timeout(Duration::from_secs(10)).unwrap();
+    assert_eq!(name.as_deref(), Some("test-poll-thread"));
+
+    // The configured thread still polls
+    let commands = device
+        .create_command_encoder(&wgpu::CommandEncoderDescriptor { label: None })
+        .finish();
+    assert_eq!(pollster::block_on(queue.submit(vec![commands])), Ok(()));
+}
+
+#[test]
+fn builder_fails_for_wrapped_devices() {
+    let (device, queue) = request_device();
+    let _wrapped = wgpu_async::wrap(Arc::clone(&device), Arc::clone(&queue));
+
+    // The configuration would be ignored, so isn't accepted
+    let res = AsyncDeviceBuilder::new().eager(true).wrap(device, queue);
+    assert_eq!(res.unwrap_err().kind(), std::io::ErrorKind::AlreadyExists);
+}
+
+/// Starts mapping a buffer, giving a future for the mapping and a receiver notified once the
+/// mapping's callback fires.
+fn map_with_notification(
+    device: &AsyncDevice,
+    buffer: &wgpu::Buffer,
+) -> (
+    wgpu_async::WgpuFuture<Result<(), wgpu::BufferAsyncError>>,
+    std::sync::mpsc::Receiver<()>,
+) {
+    let (sender, receiver) = std::sync::mpsc::channel();
+    let future = device.do_async(|callback| {
+        buffer.slice(..).map_async(wgpu::MapMode::Read, move |res| {
+            callback(res);
+            sender.send(()).unwrap();
+        })
+    });
+
+    (future, receiver)
+}
+
+fn mappable_buffer(device: &AsyncDevice) -> wgpu::Buffer {
+    device.deref().create_buffer(&wgpu::BufferDescriptor {
+        label: None,
+        size: 256,
+        usage: wgpu::BufferUsages::MAP_READ,
+        mapped_at_creation: false,
+    })
+}
+
+#[test]
+fn eager_future_progresses_before_await() {
+    let (device, _) = setup();
+
+    let buffer = mappable_buffer(&device);
+    let (future, receiver) = map_with_notification(&device, &buffer);
+    let future = future.eager();
+
+    // Never awaited, but polled for anyway
+    receiver.recv_timeout(Duration::from_secs(10)).unwrap();
+    assert_eq!(pollster::block_on(future), Ok(Ok(())));
+}
+
+#[test]
+fn eager_device_progresses_futures_before_await() {
+    let (device, queue) = request_device();
+    let (device, _) = AsyncDeviceBuilder::new()
+        .eager(true)
+        .wrap(device, queue)
+        .unwrap();
+
+    let buffer = mappable_buffer(&device);
+    let (future, receiver) = map_with_notification(&device, &buffer);
+
+    receiver.recv_timeout(Duration::from_secs(10)).unwrap();
+    assert_eq!(pollster::block_on(future), Ok(Ok(())));
+}
+
+#[test]
+fn detached_future_runs_to_completion() {
+    let (device, _) = setup();
+
+    let buffer = mappable_buffer(&device);
+    let (future, receiver) = map_with_notification(&device, &buffer);
+    future.detach();
+
+    receiver.recv_timeout(Duration::from_secs(10)).unwrap();
+}
+
+#[test]
+fn on_complete_is_given_result() {
+    let (device, _) = setup();
+
+    let buffer = mappable_buffer(&device);
+    let (sender, receiver) = std::sync::mpsc::channel();
+    device.on_complete(
+        |callback| buffer.slice(..).map_async(wgpu::MapMode::Read, callback),
+        move |res| sender.send(res).unwrap(),
+    );
+
+    let res = receiver.recv_timeout(Duration::from_secs(10)).unwrap();
+    assert_eq!(res, Ok(Ok(())));
+}
+
+#[test]
+fn on_complete_can_drop_the_last_device() {
+    let (device, queue) = request_device();
+    let (async_device, async_queue) = wgpu_async::wrap(Arc::clone(&device), queue);
+
+    let buffer = mappable_buffer(&async_device);
+    let staging_buffer = async_device.create_buffer(&wgpu::BufferDescriptor {
+        label: None,
+        size: 256,
+        usage: wgpu::BufferUsages::MAP_READ,
+        mapped_at_creation: false,
+    });
+    let (gate, gate_receiver) = std::sync::mpsc::channel();
+    let (sender, receiver) = std::sync::mpsc::channel();
+    async_device.on_complete(
+        |callback| {
+            buffer.slice(..).map_async(wgpu::MapMode::Read, move |res| {
+                // Hold the mapping up until nothing but the staging buffer holds the device
+                gate_receiver.recv().unwrap();
+                callback(res)
+            })
+        },
+        move |res| {
+            // Drops the last device on the poll thread, which stops the poll thread
+            drop(staging_buffer);
+            sender.send(res).unwrap();
+        },
+    );
+
+    drop((async_device, async_queue));
+    gate.send(()).unwrap();
+
+    let res = receiver.recv_timeout(Duration::from_secs(10)).unwrap();
+    assert_eq!(res, Ok(Ok(())));
+    assert!(AsyncDevice::from_existing(&device).is_none());
+}
+
+#[test]
+fn drop_report_omits_completed_operations() {
+    let (device, queue) = request_device();
+    let (async_device, _async_queue) = wgpu_async::wrap(Arc::clone(&device), queue);
+
+    let buffer = mappable_buffer(&async_device);
+    let (sender, receiver) = std::sync::mpsc::channel();
+    async_device.on_complete(
+        |callback| buffer.slice(..).map_async(wgpu::MapMode::Read, callback),
+        move |_| {
+            // What the device would report if this dropped the last device
+            let device = AsyncDevice::from_existing(&device).unwrap();
+            sender.send(device.pending_operations()).unwrap();
+        },
+    );
+
+    let pending = receiver.recv_timeout(Duration::from_secs(10)).unwrap();
+    assert_eq!(pending, []);
+}
+
+#[test]
+fn shared_future_resolves_every_clone() {
+    let (device, queue) = setup();
+
+    let commands = device
+        .create_command_encoder(&wgpu::CommandEncoderDescriptor { label: None })
+        .finish();
+    let submitted = queue.submit(vec![commands]).shared();
+
+    // Each task awaits from its own thread, so each has its own waker
+    let tasks = (0..4)
+        .map(|_| {
+            let submitted = submitted.clone();
+            std::thread::spawn(move || pollster::block_on(submitted))
+        })
+        .collect::<Vec<_>>();
+
+    for task in tasks {
+        assert_eq!(task.join().unwrap(), Ok(()));
+    }
+    assert_eq!(pollster::block_on(submitted), Ok(()));
+}
+
+#[test]
+fn futures_completed_from_another_thread_resolve() {
+    let (device, _) = setup();
+
+    let mut callbacks = Vec::new();
+    let futures = (0..1000)
+        .map(|i| device.do_async(|callback| callbacks.push(move || callback(i))))
+        .collect::<Vec<_>>();
+
+    // Complete while the futures are being awaited, detached or dropped
+    let completer =
+        std::thread::spawn(move || callbacks.into_iter().for_each(|callback| callback()));
+
+    let mut awaited = Vec::new();
+    for (i, future) in futures.into_iter().enumerate() {
+        match i % 3 {
+            0 => awaited.push(future),
+            1 => future.detach(),
+            _ => drop(future),
+        }
+    }
+
+    pollster::block_on(async {
+        for (i, future) in awaited.into_iter().enumerate() {
+            assert_eq!(future.await, Ok(i * 3));
+        }
+    });
+    completer.join().unwrap();
+}
+
+#[test]
+fn try_take_gives_result_without_waiting() {
+    let (device, _) = setup();
+
+    let buffer = mappable_buffer(&device);
+    let (mut future, receiver) = map_with_notification(&device, &buffer);
+
+    // Nothing polls the device yet
+    assert!(!future.is_ready());
+    assert_eq!(future.try_take(), None);
+
+    let mut future = future.eager();
+    receiver.recv_timeout(Duration::from_secs(10)).unwrap();
+
+    assert!(future.is_ready());
+    assert_eq!(future.try_take(), Some(Ok(Ok(()))));
+    assert!(!future.is_ready());
+    assert_eq!(future.try_take(), None);
+}
+
+#[cfg(feature = "futures")]
+#[test]
+fn future_is_terminated_once_taken() {
+    use futures_core::FusedFuture;
+
+    let (device, _) = setup();
+
+    let mut future = device.do_async(|callback| callback(()));
+    assert!(!future.is_terminated());
+
+    assert_eq!(pollster::block_on(&mut future), Ok(()));
+    assert!(future.is_terminated());
+}
+
+#[test]
+fn wait_blocks_until_complete() {
+    let (device, queue) = request_device();
+    // The driver is never run, so waiting must poll the device itself
+    let (device, _queue, _driver) = wgpu_async::wrap_with_driver(device, queue, sleep).unwrap();
+
+    let buffer = device.create_buffer(&wgpu::BufferDescriptor {
+        label: None,
+        size: 256,
+        usage: wgpu::BufferUsages::MAP_READ,
+        mapped_at_creation: false,
+    });
+    assert_eq!(
+        buffer.slice(..).map_async(wgpu::MapMode::Read).wait(),
+        Ok(())
+    );
+
+    // Completed from another thread rather than by polling
+    let future = device.do_async(|callback| {
+        std::thread::spawn(move || {
+            std::thread::sleep(Duration::from_millis(100));
+            callback(5)
+        });
+    });
+    assert_eq!(future.wait(), Ok(5));
+
+    // Never completed
+    let mut callbacks = Vec::new();
+    let future = device
+        .do_async(|callback: Box<dyn FnOnce(()) + Send>| callbacks.push(callback))
+        .with_timeout(Duration::from_millis(100));
+    assert_eq!(future.wait(), Err(Error::Elapsed));
+}
+
+/// Commands which keep the GPU busy for a while, running a long loop in a compute shader.
+///
+/// Some backends, such as GL on llvmpipe, run the work as it is submitted, or cut long loops short.
+#[test]
+fn wait_polls_until_complete() {
+    let (device, queue) = request_device();
+    // The driver is never run, so waiting must keep polling the device itself
+    let (device, queue, _driver) = wgpu_async::wrap_with_driver(device, queue, sleep).unwrap();
+
+    let buffer = Arc::new(mappable_buffer(&device));
+    queue.deref().submit([]);
+    let future = device.do_async(|callback| {
+        queue.on_submitted_work_done(move || {
+            // Started while polling, so only completes on a later poll
+            let mapped = Arc::clone(&buffer);
+            buffer.slice(..).map_async(wgpu::MapMode::Read, move |res| {
+                drop(mapped);
+                callback(res)
+            })
+        })
+    });
+
+    let (sender, receiver) = std::sync::mpsc::channel();
+    std::thread::spawn(move || sender.send(future.wait()));
+    let res = receiver.recv_timeout(Duration::from_secs(10)).unwrap();
+    assert_eq!(res, Ok(Ok(())));
+}
+
+fn slow_commands(device: &AsyncDevice, iterations: u32) -> wgpu::CommandBuffer {
+    let shader = device.create_shader_module(wgpu::ShaderModuleDescriptor {
+        label: None,
+        source: wgpu::ShaderSource::Wgsl(
+            format!(
+                "
+                @group(0) @binding(0) var<storage, read_write> data: u32;
+
+                @compute @workgroup_size(1)
+                fn main() {{
+                    var x = data + 1u;
+                    for (var i = 0u; i < {iterations}u; i++) {{
+                        x ^= x << 13u;
+                        x ^= x >> 17u;
+                        x ^= x << 5u;
+                    }}
+                    data = x;
+                }}
+                "
+            )
+            .into(),
+        ),
+    });
+    let pipeline = device.create_compute_pipeline(&wgpu::ComputePipelineDescriptor {
+        label: None,
+        layout: None,
+        module: &shader,
+        entry_point: None,
+        compilation_options: Default::default(),
+        cache: None,
+    });
+    let buffer = device.create_buffer(&wgpu::BufferDescriptor {
+        label: None,
+        size: 4,
+        usage: wgpu::BufferUsages::STORAGE,
+        mapped_at_creation: false,
+    });
+    let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+        label: None,
+        layout: &pipeline.get_bind_group_layout(0),
+        entries: &[wgpu::BindGroupEntry {
+            binding: 0,
+            resource: buffer.as_entire_binding(),
+        }],
+    });
+
+    let mut encoder =
+        device.create_command_encoder(&wgpu::CommandEncoderDescriptor { label: None });
+    {
+        let mut pass = encoder.begin_compute_pass(&wgpu::ComputePassDescriptor::default());
+        pass.set_pipeline(&pipeline);
+        pass.set_bind_group(0, &bind_group, &[]);
+        pass.dispatch_workgroups(1, 1, 1);
+    }
+    encoder.finish()
+}
+
+#[test]
+fn wait_gives_up_at_deadline_while_gpu_is_busy() {
+    let (device, queue) = request_device();
+    // The driver is never run, so waiting must poll the device itself
+    let (device, queue, _driver) = wgpu_async::wrap_with_driver(device, queue, sleep).unwrap();
+
+    let commands = slow_commands(&device, 1 << 28);
+    let start = std::time::Instant::now();
+    queue.deref().submit([commands]);
+
+    // Waiting for the GPU to finish would take past the deadline
+    let mut callbacks = Vec::new();
+    let future = device
+        .do_async(|callback: Box<dyn FnOnce(()) + Send>| callbacks.push(callback))
+        .with_timeout(Duration::from_millis(50));
+    assert_eq!(future.wait(), Err(Error::Elapsed));
+    assert!(start.elapsed() < Duration::from_millis(400));
+}
+
+#[test]
+fn dropped_mapping_is_unmapped() {
+    let (device, _) = setup();
+
+    let buffer = device.create_buffer(&wgpu::BufferDescriptor {
+        label: None,
+        size: 256,
+        usage: wgpu::BufferUsages::MAP_READ,
+        mapped_at_creation: false,
+    });
+
+    // Dropped before the mapping completes
+    drop(buffer.slice(..).map_async(wgpu::MapMode::Read));
+    device.poll(wgpu::Maintain::Wait);
+
+    // Mapping an already mapped buffer would be a validation error
+    pollster::block_on(buffer.slice(..).map_async(wgpu::MapMode::Read)).unwrap();
+    buffer.unmap();
+
+    // Cancelled before the mapping completes
+    assert!(buffer.slice(..).map_async(wgpu::MapMode::Read).cancel());
+    device.poll(wgpu::Maintain::Wait);
+
+    pollster::block_on(buffer.slice(..).map_async(wgpu::MapMode::Read)).unwrap();
+    buffer.unmap();
+
+    // Cancelled after the mapping completes, but before it is seen
+    let mut future = buffer.slice(..).map_async(wgpu::MapMode::Read);
+    device.poll(wgpu::Maintain::Wait);
+    assert!(future.is_ready());
+    assert!(!future.cancel());
+
+    pollster::block_on(buffer.slice(..).map_async(wgpu::MapMode::Read)).unwrap();
+    buffer.unmap();
+}
+
+#[test]
+fn cancelled_future_resolves_with_cancelled() {
+    let (device, _) = setup();
+
+    let mut callbacks = Vec::new();
+    let mut future =
+        device.do_async(|callback: Box<dyn FnOnce(()) + Send>| callbacks.push(callback));
+    assert!(future.cancel());
+    assert!(future.is_ready());
+    assert_eq!(pollster::block_on(&mut future), Err(Error::Cancelled));
+    assert!(!future.is_ready());
+
+    // The operation completing afterwards isn't seen
+    callbacks.pop().unwrap()(());
+    assert_eq!(future.try_take(), None);
+
+    // Nor is a result which was given but not taken
+    let mut future = device.do_async(|callback| callback(5));
+    assert!(!future.cancel());
+    assert_eq!(future.try_take(), Some(Err(Error::Cancelled)));
+
+    // Once taken, there is nothing left to cancel
+    let mut future = device.do_async(|callback| callback(5));
+    assert_eq!(future.try_take(), Some(Ok(5)));
+    assert!(!future.cancel());
+    assert_eq!(future.try_take(), None);
+}
+
+#[test]
+fn dispatcher_runs_completions() {
+    let (device, queue) = request_device();
+
+    // Run every completion on a thread of our own
+    let (sender, receiver) = std::sync::mpsc::channel::<Task>();
+    let sender = Mutex::new(sender);
+    std::thread::Builder::new()
+        .name("test-dispatcher".to_owned())
+        .spawn(move || receiver.into_iter().for_each(Task::run))
+        .unwrap();
+    let (device, _) = AsyncDeviceBuilder::new()
+        .dispatcher(move |task| sender.lock().unwrap().send(task).unwrap())
+        .wrap(device, queue)
+        .unwrap();
+
+    let buffer = device.create_buffer(&wgpu::BufferDescriptor {
+        label: None,
+        size: 256,
+        usage: wgpu::BufferUsages::MAP_READ,
+        mapped_at_creation: false,
+    });
+    pollster::block_on(buffer.slice(..).map_async(wgpu::MapMode::Read)).unwrap();
+    buffer.unmap();
+
+    let (sender, receiver) = std::sync::mpsc::channel();
+    device.on_complete(
+        |callback| {
+            buffer
+                .deref()
+                .slice(..)
+                .map_async(wgpu::MapMode::Read, callback)
+        },
+        move |res| {
+            let thread = std::thread::current().name().map(str::to_owned);
+            sender.send((res, thread)).unwrap()
+        },
+    );
+
+    let (res, thread) = receiver.recv_timeout(Duration::from_secs(10)).unwrap();
+    assert_eq!(res, Ok(Ok(())));
+    assert_eq!(thread.as_deref(), Some("test-dispatcher"));
+}
+
+#[test]
+fn validate_captures_validation_errors() {
+    let (device, _) = setup();
+
+    let res = pollster::block_on(device.validate(|device| {
+        device.create_buffer(&wgpu::BufferDescriptor {
+            label: None,
+            size: 256,
+            // Buffers can't be mapped for both reading and writing
+            usage: wgpu::BufferUsages::MAP_READ | wgpu::BufferUsages::MAP_WRITE,
+            mapped_at_creation: false,
+        })
+    }));
+    assert!(matches!(res, Err(Error::Validation(_))));
+
+    let res = pollster::block_on(device.validate(mappable_buffer));
+    assert!(res.is_ok());
+}
+
+#[test]
+fn scope_waits_for_operations() {
+    let (device, _) = setup();
+
+    let buffer = mappable_buffer(&device);
+    let (sender, receiver) = std::sync::mpsc::channel();
+    pollster::block_on(device.scope(|s| async move {
+        // Nothing awaits the operation, but the scope still waits for it
+        drop(s.do_async(|callback| {
+            buffer.slice(..).map_async(wgpu::MapMode::Read, move |res| {
+                sender.send(()).unwrap();
+                callback(res)
+            })
+        }));
+    }))
+    .unwrap();
+
+    assert_eq!(receiver.try_recv(), Ok(()));
+}
+
+#[test]
+fn scope_wakes_through_dispatcher() {
+    let (device, queue) = request_device();
+
+    let dispatched = Arc::new(AtomicUsize::new(0));
+    let (device, _) = AsyncDeviceBuilder::new()
+        .dispatcher({
+            let dispatched = Arc::clone(&dispatched);
+            move |task: Task| {
+                dispatched.fetch_add(1, std::sync::atomic::Ordering::SeqCst);
+                task.run()
+            }
+        })
+        .wrap(device, queue)
+        .unwrap();
+
+    pollster::block_on(device.scope(|s| async move {
+        // Complete after the scope has started waiting, so that completing has to wake it
+        drop(s.do_async(|callback| {
+            std::thread::spawn(move || {
+                std::thread::sleep(Duration::from_millis(50));
+                callback(());
+            });
+        }));
+    }))
+    .unwrap();
+
+    assert!(dispatched.load(std::sync::atomic::Ordering::SeqCst) > 0);
+}
+
+#[test]
+fn scope_tracks_unmapped_buffers() {
+    let (device, _) = setup();
+
+    let buffer = device.create_buffer(&wgpu::BufferDescriptor {
+        label: None,
+        size: 256,
+        usage: wgpu::BufferUsages::MAP_READ,
+        mapped_at_creation: false,
+    });
+    let buffer = &buffer;
+    pollster::block_on(device.scope(|s| async move {
+        s.map_async(&buffer.slice(..), wgpu::MapMode::Read)
+            .await
+            .unwrap();
+        assert!(buffer.is_mapped());
+        buffer.unmap();
+    }))
+    .unwrap();
+    assert!(!buffer.is_mapped());
+
+    // Unmapping through `wgpu` is noticed too
+    pollster::block_on(device.scope(|s| async move {
+        s.map_async(&buffer.slice(..), wgpu::MapMode::Read)
+            .await
+            .unwrap();
+        buffer.deref().unmap();
+    }))
+    .unwrap();
+}
+
+#[test]
+fn buffers_unmapped_through_deref_can_be_mapped_again() {
+    let panics = count_panics();
+    let (device, _) = setup();
+
+    let buffer = device.create_buffer(&wgpu::BufferDescriptor {
+        label: None,
+        size: 256,
+        usage: wgpu::BufferUsages::MAP_READ,
+        mapped_at_creation: false,
+    });
+    pollster::block_on(buffer.slice(..).map_async(wgpu::MapMode::Read)).unwrap();
+    buffer.deref().unmap();
+
+    let again = buffer.slice(..).map_async(wgpu::MapMode::Read);
+    assert_eq!(pollster::block_on(again), Ok(()));
+    assert!(buffer.is_mapped());
+
+    buffer.deref().unmap();
+    assert!(!buffer.is_mapped());
+    assert_eq!(panics(), 0);
+}
+
+#[test]
+fn scope_fails_if_buffer_left_mapped() {
+    let (device, _) = setup();
+
+    let buffer = device.create_buffer(&wgpu::BufferDescriptor {
+        label: None,
+        size: 256,
+        usage: wgpu::BufferUsages::MAP_READ,
+        mapped_at_creation: false,
+    });
+    let buffer = &buffer;
+    let res = pollster::block_on(device.scope(|s| async move {
+        s.map_async(&buffer.slice(..), wgpu::MapMode::Read)
+            .await
+            .unwrap();
+    }));
+    assert_eq!(res, Err(Error::LeftMapped(1)));
+}
+
+#[test]
+fn pending_operations_are_labelled() {
+    let (device, queue) = setup();
+
+    let buffer = device.create_buffer(&wgpu::BufferDescriptor {
+        label: None,
+        size: 256,
+        usage: wgpu::BufferUsages::MAP_READ,
+        mapped_at_creation: false,
+    });
+    let slice = buffer.slice(..);
+    // Submitting maintains the device, which would complete the mapping, so submit first
+    let submission = queue.submit([]);
+    let line = line!() + 1;
+    let mapping = slice.map_async(wgpu::MapMode::Read);
+
+    let operations = device.pending_operations();
+    assert_eq!(
+        operations.iter().map(|op| op.kind).collect::<Vec<_>>(),
+        [OperationKind::Submit, OperationKind::Map]
+    );
+    assert_eq!(operations[1].location.file(), file!());
+    assert_eq!(operations[1].location.line(), line);
+
+    pollster::block_on(mapping).unwrap();
+    pollster::block_on(submission).unwrap();
+    assert!(device.pending_operations().is_empty());
+}
+
+#[cfg(feature = "bytemuck")]
+#[test]
+fn typed_slices_map_to_elements() {
+    let (device, queue) = setup();
+
+    // 10 bytes, so neither the buffer length nor the slice is aligned
+    let mut buffer =
+        device.create_buffer_from_slice(&[1u16, 2, 3, 4, 5], wgpu::BufferUsages::MAP_READ);
+    assert_eq!(buffer.len(), 5);
+    // Initial contents are only copied into mappable buffers on the next submission
+    pollster::block_on(queue.submit([])).unwrap();
+
+    {
+        let slice = buffer.slice(1..4);
+        let elements = pollster::block_on(slice.map_read()).unwrap();
+        assert_eq!(*elements, [2, 3, 4]);
+    }
+    assert!(!buffer.is_mapped());
+
+    let slice = buffer.slice(4..);
+    let elements = pollster::block_on(slice.map_read()).unwrap();
+    assert_eq!(*elements, [5]);
+}
+
+#[test]
+fn mapped_guards_unmap_on_drop() {
+    let (device, queue) = setup();
+
+    let mut upload = device.create_buffer(&wgpu::BufferDescriptor {
+        label: None,
+        size: 8,
+        usage: wgpu::BufferUsages::MAP_WRITE | wgpu::BufferUsages::COPY_SRC,
+        mapped_at_creation: false,
+    });
+    let mut download = device.create_buffer(&wgpu::BufferDescriptor {
+        label: None,
+        size: 8,
+        usage: wgpu::BufferUsages::MAP_READ | wgpu::BufferUsages::COPY_DST,
+        mapped_at_creation: false,
+    });
+
+    pollster::block_on(async {
+        let mut bytes = upload.map_write(..).await.unwrap();
+        bytes.copy_from_slice(&[1, 2, 3, 4, 5, 6, 7, 8]);
+        drop(bytes);
+        assert!(!upload.is_mapped());
+
+        let mut encoder =
+            device.create_command_encoder(&wgpu::CommandEncoderDescriptor { label: None });
+        encoder.copy_buffer_to_buffer(&upload, 0, &download, 0, 8);
+        queue.submit([encoder.finish()]).await.unwrap();
+
+        let bytes = download.map_read(..).await.unwrap();
+        assert_eq!(*bytes, [1, 2, 3, 4, 5, 6, 7, 8]);
+        drop(bytes);
+        assert!(!download.is_mapped());
+    });
+}
+
+/// Counts the panics on the current thread, even those which are caught.
+fn count_panics() -> impl Fn() -> usize {
+    thread_local! {
+        static PANICS: std::cell::Cell<usize> = const { std::cell::Cell::new(0) };
+    }
+
+    let previous = std::panic::take_hook();
+    std::panic::set_hook(Box::new(move |info| {
+        PANICS.with(|panics| panics.set(panics.get() + 1));
+        previous(info)
+    }));
+    || PANICS.with(std::cell::Cell::get)
+}
+
+#[test]
+fn overlapping_maps_fail() {
+    let panics = count_panics();
+    let (device, _) = setup();
+
+    let buffer = device.create_buffer(&wgpu::BufferDescriptor {
+        label: None,
+        size: 256,
+        usage: wgpu::BufferUsages::MAP_READ,
+        mapped_at_creation: false,
+    });
+
+    let first = buffer.slice(..128).map_async(wgpu::MapMode::Read);
+    let second = buffer.slice(128..).map_async(wgpu::MapMode::Read);
+    assert_eq!(pollster::block_on(second), Err(Error::AlreadyMapped));
+
+    pollster::block_on(first).unwrap();
+    let again = buffer.slice(..).map_async(wgpu::MapMode::Read);
+    assert_eq!(pollster::block_on(again), Err(Error::AlreadyMapped));
+
+    buffer.unmap();
+    pollster::block_on(buffer.slice(..).map_async(wgpu::MapMode::Read)).unwrap();
+
+    let created_mapped = device.create_buffer(&wgpu::BufferDescriptor {
+        label: None,
+        size: 256,
+        usage: wgpu::BufferUsages::MAP_READ,
+        mapped_at_creation: true,
+    });
+    let mapping = created_mapped.slice(..).map_async(wgpu::MapMode::Read);
+    assert_eq!(pollster::block_on(mapping), Err(Error::AlreadyMapped));
+
+    // Overlapping maps are caught before `wgpu` is asked, rather than by catching its panic
+    assert_eq!(panics(), 0);
+}
+
+#[test]
+fn read_buffer_reads_unaligned_ranges() {
+    let (device, queue) = setup();
+
+    let data = (0..64).collect::<Vec<u8>>();
+    let buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+        label: None,
+        contents: &data,
+        usage: wgpu::BufferUsages::COPY_SRC,
+    });
+
+    pollster::block_on(async {
+        assert_eq!(queue.read_buffer(&buffer, ..).await.unwrap(), data);
+        assert_eq!(queue.read_buffer(&buffer, 3..9).await.unwrap(), data[3..9]);
+        assert_eq!(queue.read_buffer(&buffer, 5..=5).await.unwrap(), [5]);
+        assert!(queue.read_buffer(&buffer, 7..7).await.unwrap().is_empty());
+    });
+
+    // The mapping is started by the call, and labelled with where the read was started
+    let line = line!() + 1;
+    let read = queue.read_buffer(&buffer, ..);
+    let operations = device.pending_operations();
+    assert_eq!(operations.len(), 1);
+    assert_eq!(operations[0].location.file(), file!());
+    assert_eq!(operations[0].location.line(), line);
+    assert_eq!(pollster::block_on(read).unwrap(), data);
+}
+
+#[cfg(feature = "bytemuck")]
+#[test]
+fn read_typed_buffer_gives_elements() {
+    let (device, queue) = setup();
+
+    let buffer =
+        device.create_buffer_from_slice(&[1.0f32, 2.0, 3.0, 4.0], wgpu::BufferUsages::COPY_SRC);
+    let elements = pollster::block_on(queue.read_typed_buffer(&buffer, 1..3)).unwrap();
+    assert_eq!(elements, [2.0, 3.0]);
+}
+
+#[test]
+fn read_texture_removes_row_padding() {
+    let (device, queue) = setup();
+
+    let size = wgpu::Extent3d {
+        width: 3,
+        height: 2,
+        depth_or_array_layers: 1,
+    };
+    let texture = device.create_texture(&wgpu::TextureDescriptor {
+        label: None,
+        size,
+        mip_level_count: 1,
+        sample_count: 1,
+        dimension: wgpu::TextureDimension::D2,
+        format: wgpu::TextureFormat::Rgba8Unorm,
+        usage: wgpu::TextureUsages::COPY_SRC | wgpu::TextureUsages::COPY_DST,
+        view_formats: &[],
+    });
+    let pixels = (0..24).collect::<Vec<u8>>();
+    queue.write_texture(
+        texture.as_image_copy(),
+        &pixels,
+        wgpu::ImageDataLayout {
+            offset: 0,
+            bytes_per_row: Some(12),
+            rows_per_image: None,
+        },
+        size,
+    );
+
+    pollster::block_on(async {
+        let read = queue
+            .read_texture(&texture, 0, wgpu::Origin3d::ZERO, size)
+            .await
+            .unwrap();
+        assert_eq!(read.data, pixels);
+        assert_eq!(read.format, wgpu::TextureFormat::Rgba8Unorm);
+        assert_eq!((read.bytes_per_row, read.rows_per_image), (12, 2));
+
+        let region = wgpu::Extent3d {
+            width: 2,
+            height: 1,
+            depth_or_array_layers: 1,
+        };
+        let read = queue
+            .read_texture(&texture, 0, wgpu::Origin3d { x: 1, y: 1, z: 0 }, region)
+            .await
+            .unwrap();
+        assert_eq!(read.data, pixels[16..24]);
+    });
+}
+
+#[test]
+fn read_texture_removes_padding_from_wide_rows() {
+    let (device, queue) = setup();
+
+    // 65 pixels make 260 byte rows, padded to 512 bytes when copied
+    let size = wgpu::Extent3d {
+        width: 65,
+        height: 3,
+        depth_or_array_layers: 1,
+    };
+    let texture = device.create_texture(&wgpu::TextureDescriptor {
+        label: None,
+        size,
+        mip_level_count: 1,
+        sample_count: 1,
+        dimension: wgpu::TextureDimension::D2,
+        format: wgpu::TextureFormat::Rgba8Unorm,
+        usage: wgpu::TextureUsages::COPY_SRC | wgpu::TextureUsages::COPY_DST,
+        view_formats: &[],
+    });
+    let pixels = (0..65 * 4 * 3)
+        .map(|i| (i % 251) as u8)
+        .collect::<Vec<u8>>();
+    queue.write_texture(
+        texture.as_image_copy(),
+        &pixels,
+        wgpu::ImageDataLayout {
+            offset: 0,
+            bytes_per_row: Some(65 * 4),
+            rows_per_image: None,
+        },
+        size,
+    );
+
+    let read =
+        pollster::block_on(queue.read_texture(&texture, 0, wgpu::Origin3d::ZERO, size)).unwrap();
+    assert_eq!((read.bytes_per_row, read.rows_per_image), (260, 3));
+    assert_eq!(read.data, pixels);
+}
+
+#[test]
+fn read_texture_reads_every_layer() {
+    let (device, queue) = setup();
+
+    let size = wgpu::Extent3d {
+        width: 2,
+        height: 2,
+        depth_or_array_layers: 3,
+    };
+    let texture = device.create_texture(&wgpu::TextureDescriptor {
+        label: None,
+        size,
+        mip_level_count: 1,
+        sample_count: 1,
+        dimension: wgpu::TextureDimension::D2,
+        format: wgpu::TextureFormat::R8Uint,
+        usage: wgpu::TextureUsages::COPY_SRC | wgpu::TextureUsages::COPY_DST,
+        view_formats: &[],
+    });
+    let pixels = (0..12).collect::<Vec<u8>>();
+    queue.write_texture(
+        texture.as_image_copy(),
+        &pixels,
+        wgpu::ImageDataLayout {
+            offset: 0,
+            bytes_per_row: Some(2),
+            rows_per_image: Some(2),
+        },
+        size,
+    );
+
+    pollster::block_on(async {
+        let read = queue
+            .read_texture(&texture, 0, wgpu::Origin3d::ZERO, size)
+            .await
+            .unwrap();
+        assert_eq!(read.data, pixels);
+        assert_eq!((read.bytes_per_row, read.rows_per_image), (2, 2));
+
+        // The last two layers, starting from the second
+        let layers = wgpu::Extent3d {
+            depth_or_array_layers: 2,
+            ..size
+        };
+        let read = queue
+            .read_texture(&texture, 0, wgpu::Origin3d { x: 0, y: 0, z: 1 }, layers)
+            .await
+            .unwrap();
+        assert_eq!(read.data, pixels[4..]);
+    });
+}
+
+#[test]
+fn read_texture_aspect_reads_depth_and_stencil() {
+    let downlevel = request_adapter().get_downlevel_capabilities();
+    if !downlevel
+        .flags
+        .contains(wgpu::DownlevelFlags::DEPTH_TEXTURE_AND_BUFFER_COPIES)
+    {
+        return;
+    }
+    let (device, queue) = setup();
+
+    let size = wgpu::Extent3d {
+        width: 4,
+        height: 2,
+        depth_or_array_layers: 1,
+    };
+    let depth_stencil = |format| {
+        device.create_texture(&wgpu::TextureDescriptor {
+            label: None,
+            size,
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            format,
+            usage: wgpu::TextureUsages::COPY_SRC | wgpu::TextureUsages::RENDER_ATTACHMENT,
+            view_formats: &[],
+        })
+    };
+    let depth = depth_stencil(wgpu::TextureFormat::Depth32Float);
+    let stencil = depth_stencil(wgpu::TextureFormat::Depth24PlusStencil8);
+
+    // Clear each texture with a render pass which draws nothing
+    let mut encoder =
+        device.create_command_encoder(&wgpu::CommandEncoderDescriptor { label: None });
+    for (texture, depth_ops, stencil_ops) in [
+        (
+            &depth,
+            Some(wgpu::Operations {
+                load: wgpu::LoadOp::Clear(0.5),
+                store: wgpu::StoreOp::Store,
+            }),
+            None,
+        ),
+        (
+            &stencil,
+            Some(wgpu::Operations {
+                load: wgpu::LoadOp::Clear(1.0),
+                store: wgpu::StoreOp::Store,
+            }),
+            Some(wgpu::Operations {
+                load: wgpu::LoadOp::Clear(7),
+                store: wgpu::StoreOp::Store,
+            }),
+        ),
+    ] {
+        let view = texture.create_view(&wgpu::TextureViewDescriptor::default());
+        encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+            label: None,
+            color_attachments: &[],
+            depth_stencil_attachment: Some(wgpu::RenderPassDepthStencilAttachment {
+                view: &view,
+                depth_ops,
+                stencil_ops,
+            }),
+            timestamp_writes: None,
+            occlusion_query_set: None,
+        });
+    }
+    queue.submit([encoder.finish()]);
+
+    pollster::block_on(async {
+        let read = queue
+            .read_texture_aspect(
+                &depth,
+                wgpu::TextureAspect::DepthOnly,
+                0,
+                wgpu::Origin3d::ZERO,
+                size,
+            )
+            .await
+            .unwrap();
+        assert_eq!(read.format, wgpu::TextureFormat::Depth32Float);
+        assert_eq!(read.bytes_per_row, 16);
+        assert_eq!(read.data, 0.5f32.to_ne_bytes().repeat(8));
+
+        let read = queue
+            .read_texture_aspect(
+                &stencil,
+                wgpu::TextureAspect::StencilOnly,
+                0,
+                wgpu::Origin3d::ZERO,
+                size,
+            )
+            .await
+            .unwrap();
+        assert_eq!(read.format, wgpu::TextureFormat::Stencil8);
+        assert_eq!(read.bytes_per_row, 4);
+        assert_eq!(read.data, [7; 8]);
+    });
+}
+
+#[test]
+fn read_texture_rejects_uncopyable_aspects() {
+    let (device, queue) = setup();
+
+    let size = wgpu::Extent3d {
+        width: 4,
+        height: 2,
+        depth_or_array_layers: 1,
+    };
+    let texture = |format| {
+        device.create_texture(&wgpu::TextureDescriptor {
+            label: None,
+            size,
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            format,
+            usage: wgpu::TextureUsages::COPY_SRC | wgpu::TextureUsages::RENDER_ATTACHMENT,
+            view_formats: &[],
+        })
+    };
+    let depth = texture(wgpu::TextureFormat::Depth24Plus);
+    let depth_stencil = texture(wgpu::TextureFormat::Depth24PlusStencil8);
+
+    pollster::block_on(async {
+        let read = queue.read_texture(&depth, 0, wgpu::Origin3d::ZERO, size);
+        assert!(matches!(read.await, Err(Error::Unsupported(_))));
+
+        let read = queue.read_texture(&depth_stencil, 0, wgpu::Origin3d::ZERO, size);
+        assert!(matches!(read.await, Err(Error::Unsupported(_))));
+
+        let read = queue.read_texture_aspect(
+            &depth_stencil,
+            wgpu::TextureAspect::DepthOnly,
+            0,
+            wgpu::Origin3d::ZERO,
+            size,
+        );
+        assert!(matches!(read.await, Err(Error::Unsupported(_))));
+    });
+}
+
+#[test]
+fn read_texture_reads_compressed_blocks() {
+    let Some((device, queue)) = request_device_with(wgpu::Features::TEXTURE_COMPRESSION_BC) else {
+        return;
+    };
+    let (device, queue) = wgpu_async::wrap(device, queue);
+    // GL can't copy compressed textures into buffers, so only the layout can be checked there
+    let can_read_blocks = request_adapter().get_info().backend != wgpu::Backend::Gl;
+
+    // 8x8 pixels of BC1 are 2x2 blocks of 8 bytes each
+    let size = wgpu::Extent3d {
+        width: 8,
+        height: 8,
+        depth_or_array_layers: 1,
+    };
+    let texture = device.create_texture(&wgpu::TextureDescriptor {
+        label: None,
+        size,
+        mip_level_count: 1,
+        sample_count: 1,
+        dimension: wgpu::TextureDimension::D2,
+        format: wgpu::TextureFormat::Bc1RgbaUnorm,
+        usage: wgpu::TextureUsages::COPY_SRC | wgpu::TextureUsages::COPY_DST,
+        view_formats: &[],
+    });
+    let blocks = (0..32).collect::<Vec<u8>>();
+    queue.write_texture(
+        texture.as_image_copy(),
+        &blocks,
+        wgpu::ImageDataLayout {
+            offset: 0,
+            bytes_per_row: Some(16),
+            rows_per_image: None,
+        },
+        size,
+    );
+
+    pollster::block_on(async {
+        let read = queue
+            .read_texture(&texture, 0, wgpu::Origin3d::ZERO, size)
+            .await
+            .unwrap();
+        assert_eq!((read.bytes_per_row, read.rows_per_image), (16, 2));
+        assert_eq!(read.data.len(), blocks.len());
+        if !can_read_blocks {
+            return;
+        }
+        assert_eq!(read.data, blocks);
+
+        // The bottom right block
+        let block = wgpu::Extent3d {
+            width: 4,
+            height: 4,
+            depth_or_array_layers: 1,
+        };
+        let read = queue
+            .read_texture(&texture, 0, wgpu::Origin3d { x: 4, y: 4, z: 0 }, block)
+            .await
+            .unwrap();
+        assert_eq!(read.data, blocks[24..]);
+    });
+}
+
+fn mipped_texture_descriptor() -> wgpu::TextureDescriptor<'static> {
+    wgpu::TextureDescriptor {
+        label: None,
+        size: wgpu::Extent3d {
+            width: 4,
+            height: 4,
+            depth_or_array_layers: 1,
+        },
+        mip_level_count: 3,
+        sample_count: 1,
+        dimension: wgpu::TextureDimension::D2,
+        format: wgpu::TextureFormat::Rgba8Unorm,
+        usage: wgpu::TextureUsages::COPY_SRC,
+        view_formats: &[],
+    }
+}
+
+fn read_mip(queue: &AsyncQueue, texture: &wgpu::Texture, mip_level: u32) -> Vec<u8> {
+    let size = texture
+        .size()
+        .mip_level_size(mip_level, texture.dimension());
+    pollster::block_on(queue.read_texture(texture, mip_level, wgpu::Origin3d::ZERO, size))
+        .unwrap()
+        .data
+}
+
+#[test]
+fn texture_upload_resolves_with_every_mip_level() {
+    let (device, queue) = setup();
+
+    // 4x4, 2x2 then 1x1, with a different value in each level
+    let data = [[10; 64].as_slice(), &[20; 16], &[30; 4]].concat();
+    let texture = pollster::block_on(device.create_texture_with_data_async(
+        &queue,
+        &mipped_texture_descriptor(),
+        &data,
+        MipSource::Data(wgpu::util::TextureDataOrder::LayerMajor),
+    ))
+    .unwrap();
+
+    assert_eq!(read_mip(&queue, &texture, 0), [10; 64]);
+    assert_eq!(read_mip(&queue, &texture, 1), [20; 16]);
+    assert_eq!(read_mip(&queue, &texture, 2), [30; 4]);
+}
+
+#[test]
+fn texture_upload_generates_mip_levels() {
+    let (device, queue) = setup();
+
+    let texture = pollster::block_on(device.create_texture_with_data_async(
+        &queue,
+        &mipped_texture_descriptor(),
+        &[200; 64],
+        MipSource::Generate,
+    ))
+    .unwrap();
+
+    // Downsampling a single colour gives the same colour
+    assert_eq!(read_mip(&queue, &texture, 1), [200; 16]);
+    assert_eq!(read_mip(&queue, &texture, 2), [200; 4]);
+
+    // Again, reusing the pipeline made for the first upload
+    let texture = pollster::block_on(device.create_texture_with_data_async(
+        &queue,
+        &mipped_texture_descriptor(),
+        &[50; 64],
+        MipSource::Generate,
+    ))
+    .unwrap();
+    assert_eq!(read_mip(&queue, &texture, 2), [50; 4]);
+}
+
+#[test]
+fn texture_upload_is_labelled_with_caller() {
+    let (device, queue) = setup();
+
+    let line = line!() + 1;
+    let upload = device.create_texture_with_data_async(
+        &queue,
+        &mipped_texture_descriptor(),
+        &[0; 84],
+        MipSource::Data(wgpu::util::TextureDataOrder::LayerMajor),
+    );
+
+    // Submitted as soon as the upload is started, rather than once awaited
+    let operations = device.pending_operations();
+    assert_eq!(operations.len(), 1);
+    assert_eq!(operations[0].kind, OperationKind::Submit);
+    assert_eq!(operations[0].location.file(), file!());
+    assert_eq!(operations[0].location.line(), line);
+
+    pollster::block_on(upload).unwrap();
+}
+
+#[test]
+fn texture_upload_rejects_unsupported_textures() {
+    let (device, queue) = setup();
+    let upload = |desc: &wgpu::TextureDescriptor<'_>, mips| {
+        pollster::block_on(device.create_texture_with_data_async(&queue, desc, &[0; 4096], mips))
+    };
+    let unsupported = |res: Result<wgpu::Texture, Error>| matches!(res, Err(Error::Unsupported(_)));
+
+    // Not filterable, so mip levels can't be generated by sampling
+    let float = wgpu::TextureDescriptor {
+        format: wgpu::TextureFormat::Rgba32Float,
+        ..mipped_texture_descriptor()
+    };
+    assert!(unsupported(upload(&float, MipSource::Generate)));
+
+    let volume = wgpu::TextureDescriptor {
+        dimension: wgpu::TextureDimension::D3,
+        ..mipped_texture_descriptor()
+    };
+    assert!(unsupported(upload(&volume, MipSource::Generate)));
+
+    let depth_stencil = wgpu::TextureDescriptor {
+        format: wgpu::TextureFormat::Depth24PlusStencil8,
+        mip_level_count: 1,
+        ..mipped_texture_descriptor()
+    };
+    let order = MipSource::Data(wgpu::util::TextureDataOrder::LayerMajor);
+    assert!(unsupported(upload(&depth_stencil, order)));
+}
@@ -6,9 +6,18 @@ use test;
 #[cfg(target_arch = "wasm32")]
 use wasm_bindgen_test::wasm_bindgen_test as test;
 
-use std::sync::Arc;
-
-use wgpu_async::{AsyncDevice, AsyncQueue};
+// Without atomics the web is single-threaded, so the device and queue are shared with `Rc`s
+#[cfg(all(target_arch = "wasm32", not(target_feature = "atomics")))]
+use std::rc::Rc as Shared;
+#[cfg(not(all(target_arch = "wasm32", not(target_feature = "atomics"))))]
+use std::sync::Arc as Shared;
+
+#[cfg(not(all(target_arch = "wasm32", not(target_feature = "atomics"))))]
+use wgpu_async::{wrap, AsyncDevice, AsyncQueue};
+#[cfg(all(target_arch = "wasm32", not(target_feature = "atomics")))]
+use wgpu_async::{
+    wrap_local as wrap, LocalAsyncDevice as AsyncDevice, LocalAsyncQueue as AsyncQueue,
+};
 
 fn block_on<F: std::future::Future<Output = ()> + 'static>(f: F) {
     #[cfg(target_arch = "wasm32")]
@@ -39,10 +48,7 @@ async fn setup() -> (AsyncDevice, AsyncQueue) {
         )
         .await
         .expect("missing device");
-    // TODO: Look at swapping to `Rc` on web
-    #[allow(clippy::arc_with_non_send_sync)]
-    let (device, queue) = (Arc::new(device), Arc::new(queue));
-    wgpu_async::wrap(Arc::clone(&device), Arc::clone(&queue))
+    wrap(Shared::new(device), Shared::new(queue))
 }
 
 #[self::test]
@@ -108,7 +114,7 @@ fn await_map_write_copy_await_map_read() {
         let mut commands =
             device.create_command_encoder(&wgpu::CommandEncoderDescriptor { label: None });
         commands.copy_buffer_to_buffer(&async_buffer1, 0, &async_buffer2, 0, 128 * 1024);
-        queue.submit(vec![commands.finish()]).await;
+        queue.submit(vec![commands.finish()]).await.unwrap();
 
         // Read copied data
         async_buffer2
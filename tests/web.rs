@@ -0,0 +1,64 @@
+#![cfg(all(target_arch = "wasm32", not(target_feature = "atomics")))]
+
+wasm_bindgen_test::wasm_bindgen_test_configure!(run_in_browser);
+use wasm_bindgen_test::wasm_bindgen_test as test;
+
+use std::rc::Rc;
+
+use wgpu_async::{LocalAsyncDevice, LocalAsyncQueue};
+
+async fn setup() -> (LocalAsyncDevice, LocalAsyncQueue) {
+    let instance = wgpu::Instance::new(wgpu::InstanceDescriptor::default());
+    let adapter = instance
+        .request_adapter(&wgpu::RequestAdapterOptions {
+            power_preference: wgpu::PowerPreference::HighPerformance,
+            compatible_surface: None,
+            force_fallback_adapter: true,
+        })
+        .await
+        .expect("missing adapter");
+    let (device, queue) = adapter
+        .request_device(
+            &wgpu::DeviceDescriptor {
+                required_features: wgpu::Features::empty(),
+                required_limits: adapter.limits(),
+                label: None,
+                memory_hints: wgpu::MemoryHints::default(),
+            },
+            None,
+        )
+        .await
+        .expect("missing device");
+    wgpu_async::wrap_local(Rc::new(device), Rc::new(queue))
+}
+
+#[self::test]
+async fn local_submit_resolves() {
+    let (device, queue) = setup().await;
+
+    let encoder = device.create_command_encoder(&wgpu::CommandEncoderDescriptor { label: None });
+    queue.submit([encoder.finish()]).await.unwrap();
+}
+
+#[self::test]
+async fn local_futures_allow_non_send_results() {
+    let (device, _) = setup().await;
+
+    let buffer = device.create_buffer(&wgpu::BufferDescriptor {
+        label: None,
+        size: 8,
+        usage: wgpu::BufferUsages::MAP_READ,
+        mapped_at_creation: false,
+    });
+
+    // `Rc` isn't `Send`, so couldn't be the result of an `AsyncDevice` future
+    let result = device
+        .do_async(|callback| {
+            buffer
+                .slice(..)
+                .map_async(wgpu::MapMode::Read, move |res| callback(Rc::new(res)))
+        })
+        .await
+        .unwrap();
+    assert!(result.is_ok());
+}